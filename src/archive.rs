@@ -0,0 +1,654 @@
+use flate2::read::GzDecoder;
+use glob::glob;
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::fs::File;
+use std::io::{Error, Read};
+use std::path::{Path, PathBuf};
+
+use crate::metadata;
+use crate::parents::ParentRecords;
+use crate::{Attachment, Release};
+
+const FIRST_ATTACHMENTS_METADATA_FILENAME: &str = "attachments_000001.json";
+const ATTACHMENTS_DIRECTORY_NAME: &str = "attachments";
+const FIRST_RELEASES_METADATA_FILENAME: &str = "releases_000001.json";
+const RELEASE_ASSETS_DIRECTORY_NAME: &str = "release_assets";
+const TARBALL_ROOT_PREFIX: &str = "tarball://root/";
+
+/// A GitHub migration archive, either extracted into a directory or still packed up as a
+/// `.tar.gz` (or plain `.tar`) file.
+pub enum Archive {
+    Directory(PathBuf),
+    // Tarballs are read in a single pass, so we record the size (and, if asked, the SHA-256 hash)
+    // of every attachment and release asset file as we come across it, keyed by its path inside
+    // the archive, to look them up later. If asked, we also keep the records needed to look up
+    // attachments' parents.
+    Tarball {
+        path: PathBuf,
+        entry_sizes: HashMap<String, u64>,
+        entry_hashes: HashMap<String, String>,
+        parent_records: Option<ParentRecords>,
+    },
+}
+
+/// Options controlling how an archive is read.
+#[derive(Debug, Default)]
+pub struct OpenOptions {
+    /// Hash every attachment and release asset file as a tarball is read, so `asset_hash` can be
+    /// used
+    pub hash_assets: bool,
+    /// Skip metadata files that can't be parsed with a warning, rather than returning an error
+    pub skip_invalid_metadata: bool,
+    /// Keep the issue, pull request, comment and review records from a tarball's metadata files as
+    /// it's read, so `parents::resolve_parents` doesn't have to read it again
+    pub collect_parent_records: bool,
+}
+
+impl Archive {
+    /// Opens the archive at `path`, passing each of the attachments listed in its
+    /// `attachments_*.json` metadata files and the release assets listed in its `releases_*.json`
+    /// metadata files to `on_attachment`, alongside the archive.
+    ///
+    /// By the time an attachment is passed on, `asset_size` and `asset_hash` can look up its file,
+    /// if the archive has it. For extracted archives, attachments are passed on in the order
+    /// they're listed in the metadata files. Tarballs can only be read in a single pass, so an
+    /// attachment whose file comes later in the tarball than its metadata file is held back until
+    /// we reach the file, and any whose files never turn up are passed on at the end.
+    pub fn open(
+        path: &Path,
+        options: &OpenOptions,
+        on_attachment: impl FnMut(&Archive, Attachment),
+    ) -> Result<Archive, Error> {
+        if is_tarball(path) {
+            read_tarball(path, options, on_attachment)
+        } else if has_tarball_extension(path) && !path.exists() {
+            // Otherwise, we'd go looking for metadata files in a directory with the tarball's name
+            let error_message = format!("Could not find archive file `{}`", path.display());
+            Err(Error::other(error_message))
+        } else {
+            read_directory(path, options, on_attachment)
+        }
+    }
+
+    /// Returns where the file for a `tarball://root/` asset URL lives - on disk for an extracted
+    /// archive, or inside the tarball otherwise.
+    pub fn asset_path(&self, asset_url: &str) -> String {
+        let entry_path = asset_entry_path(asset_url);
+
+        match self {
+            Archive::Directory(working_directory) => {
+                working_directory.join(entry_path).display().to_string()
+            }
+            Archive::Tarball { .. } => entry_path,
+        }
+    }
+
+    /// Returns the size in bytes of the file for a `tarball://root/` asset URL, or `None` if
+    /// it isn't in the archive.
+    pub fn asset_size(&self, asset_url: &str) -> Option<u64> {
+        match self {
+            Archive::Directory(_) => fs::metadata(self.asset_path(asset_url))
+                .ok()
+                .map(|metadata| metadata.len()),
+            Archive::Tarball { entry_sizes, .. } => {
+                entry_sizes.get(&self.asset_path(asset_url)).copied()
+            }
+        }
+    }
+
+    /// Returns the hex-encoded SHA-256 hash of the file for a `tarball://root/` asset URL, or
+    /// `None` if it isn't in the archive (or the tarball was opened without `hash_assets`).
+    pub fn asset_hash(&self, asset_url: &str) -> Option<String> {
+        match self {
+            Archive::Directory(_) => File::open(self.asset_path(asset_url))
+                .and_then(sha256_hex)
+                .ok(),
+            Archive::Tarball { entry_hashes, .. } => {
+                entry_hashes.get(&self.asset_path(asset_url)).cloned()
+            }
+        }
+    }
+
+    /// Returns the path and size in bytes of every file in the archive's `attachments/` and
+    /// `release_assets/` directories, whether or not the metadata files mention them. Paths are in
+    /// the same form as `asset_path`.
+    pub fn asset_files(&self) -> Result<Vec<(String, u64)>, Error> {
+        match self {
+            Archive::Directory(working_directory) => {
+                let mut asset_files: Vec<(String, u64)> = Vec::new();
+
+                for directory_name in [ATTACHMENTS_DIRECTORY_NAME, RELEASE_ASSETS_DIRECTORY_NAME] {
+                    let directory_path = working_directory.join(directory_name);
+
+                    if directory_path.is_dir() {
+                        list_files(&directory_path, &mut asset_files)?;
+                    }
+                }
+
+                Ok(asset_files)
+            }
+            Archive::Tarball { entry_sizes, .. } => Ok(entry_sizes
+                .iter()
+                .map(|(entry_path, size)| (entry_path.clone(), *size))
+                .collect()),
+        }
+    }
+
+    /// Passes each of the archive's metadata files for the given models (e.g. `issues_000001.json`
+    /// for `issues`) to `on_file`, alongside its file name.
+    pub fn for_each_metadata_file(
+        &self,
+        model_names: &[&str],
+        mut on_file: impl FnMut(&str, &mut dyn Read) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        match self {
+            Archive::Directory(working_directory) => {
+                for model_name in model_names {
+                    for path in metadata_file_paths(working_directory, model_name) {
+                        let file_name = path
+                            .file_name()
+                            .map(|file_name| file_name.to_string_lossy().to_string())
+                            .unwrap_or_default();
+
+                        on_file(&file_name, &mut File::open(&path)?)?;
+                    }
+                }
+            }
+            // We've already been through the tarball once, so we have to start again from the top
+            Archive::Tarball { path, .. } => {
+                let mut tarball = tar::Archive::new(open_tarball(path)?);
+
+                for entry in tarball.entries()? {
+                    let mut entry = entry?;
+                    let entry_path = normalize_entry_path(&entry.path()?);
+
+                    if model_names
+                        .iter()
+                        .any(|model_name| is_metadata_filename(&entry_path, model_name))
+                    {
+                        on_file(&entry_path, &mut entry)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the files for the given `tarball://root/` asset URLs, returning their contents keyed by
+    /// asset URL. Files which aren't in the archive are left out.
+    pub fn read_assets(
+        &self,
+        asset_urls: &HashSet<&str>,
+    ) -> Result<HashMap<String, Vec<u8>>, Error> {
+        let mut contents: HashMap<String, Vec<u8>> = HashMap::new();
+
+        if asset_urls.is_empty() {
+            return Ok(contents);
+        }
+
+        match self {
+            Archive::Directory(_) => {
+                for asset_url in asset_urls {
+                    if let Ok(asset_contents) = fs::read(self.asset_path(asset_url)) {
+                        contents.insert(asset_url.to_string(), asset_contents);
+                    }
+                }
+            }
+            Archive::Tarball { .. } => {
+                let asset_urls_by_entry_path: HashMap<String, &str> = asset_urls
+                    .iter()
+                    .map(|asset_url| (asset_entry_path(asset_url), *asset_url))
+                    .collect();
+
+                self.for_each_entry(|entry| {
+                    if let Some(asset_url) = asset_urls_by_entry_path.get(&entry.entry_path) {
+                        let mut asset_contents = Vec::new();
+                        entry.reader.read_to_end(&mut asset_contents)?;
+                        contents.insert(asset_url.to_string(), asset_contents);
+                    }
+
+                    Ok(())
+                })?;
+            }
+        }
+
+        Ok(contents)
+    }
+
+    /// Passes every file and directory in the archive to `on_entry`, in the order they appear in
+    /// the tarball (or, for an extracted archive, sorted by path).
+    pub fn for_each_entry(
+        &self,
+        mut on_entry: impl FnMut(Entry) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        match self {
+            Archive::Directory(working_directory) => {
+                walk_entries(working_directory, Path::new(""), &mut on_entry)
+            }
+            // We've already been through the tarball once, so we have to start again from the top
+            Archive::Tarball { path, .. } => {
+                let mut tarball = tar::Archive::new(open_tarball(path)?);
+
+                for entry in tarball.entries()? {
+                    let mut entry = entry?;
+                    let path = entry.path()?.to_path_buf();
+
+                    on_entry(Entry {
+                        entry_path: normalize_entry_path(&path),
+                        path,
+                        header: entry.header().clone(),
+                        reader: &mut entry,
+                    })?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the issue, pull request, comment and review records collected when a tarball was
+    /// opened with `collect_parent_records`, or `None` if they weren't collected.
+    pub fn parent_records(&self) -> Option<&ParentRecords> {
+        match self {
+            Archive::Directory(_) => None,
+            Archive::Tarball { parent_records, .. } => parent_records.as_ref(),
+        }
+    }
+
+    /// Describes the archive for use in messages, e.g. `migration.tar.gz`.
+    pub fn describe(&self) -> String {
+        match self {
+            Archive::Directory(working_directory) => working_directory.display().to_string(),
+            Archive::Tarball { path, .. } => path.display().to_string(),
+        }
+    }
+
+    // Records the size (and, if we're hashing, the hash) of a file we've come across while reading
+    // a tarball
+    fn record_entry(&mut self, entry_path: &str, size: u64, sha256: Option<String>) {
+        if let Archive::Tarball {
+            entry_sizes,
+            entry_hashes,
+            ..
+        } = self
+        {
+            entry_sizes.insert(entry_path.to_string(), size);
+
+            if let Some(sha256) = sha256 {
+                entry_hashes.insert(entry_path.to_string(), sha256);
+            }
+        }
+    }
+}
+
+/// A file or directory in an archive, as passed to `Archive::for_each_entry`.
+pub struct Entry<'a> {
+    /// Where the entry lives in the archive, exactly as it's written in the tarball
+    pub path: PathBuf,
+    /// `path` in the same form as `tarball://root/` asset URLs and `is_metadata_filename`
+    pub entry_path: String,
+    /// The entry's tar header. For extracted archives, this is built from the file on disk.
+    pub header: tar::Header,
+    pub reader: &'a mut dyn Read,
+}
+
+// Recursively lists the files in a directory with their sizes. We build the paths up with `join`
+// (rather than using `glob`, which drops any leading `./`) so they match `Archive::asset_path`.
+fn list_files(directory_path: &Path, files: &mut Vec<(String, u64)>) -> Result<(), Error> {
+    for entry in fs::read_dir(directory_path)? {
+        let path = directory_path.join(entry?.file_name());
+        let metadata = fs::metadata(&path)?;
+
+        if metadata.is_dir() {
+            list_files(&path, files)?;
+        } else if metadata.is_file() {
+            files.push((path.display().to_string(), metadata.len()));
+        }
+    }
+
+    Ok(())
+}
+
+// Recursively passes the files and directories in `relative_path` (inside `working_directory`) to
+// `on_entry`, with tar headers built from the files on disk
+fn walk_entries(
+    working_directory: &Path,
+    relative_path: &Path,
+    on_entry: &mut impl FnMut(Entry) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut file_names: Vec<_> = fs::read_dir(working_directory.join(relative_path))?
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<Result<_, _>>()?;
+    file_names.sort();
+
+    for file_name in file_names {
+        let path = relative_path.join(file_name);
+        let metadata = fs::metadata(working_directory.join(&path))?;
+
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(&metadata);
+
+        if metadata.is_dir() {
+            on_entry(Entry {
+                entry_path: normalize_entry_path(&path),
+                path: path.clone(),
+                header,
+                reader: &mut std::io::empty(),
+            })?;
+            walk_entries(working_directory, &path, on_entry)?;
+        } else if metadata.is_file() {
+            on_entry(Entry {
+                entry_path: normalize_entry_path(&path),
+                path: path.clone(),
+                header,
+                reader: &mut File::open(working_directory.join(&path))?,
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the path inside the archive of the file for a `tarball://root/` asset URL, e.g.
+/// `attachments/<uuid>/image.jpg`.
+pub fn asset_entry_path(asset_url: &str) -> String {
+    asset_url.replace(TARBALL_ROOT_PREFIX, "")
+}
+
+fn has_tarball_extension(path: &Path) -> bool {
+    let file_name = path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz") || file_name.ends_with(".tar")
+}
+
+fn is_tarball(path: &Path) -> bool {
+    path.is_file() && has_tarball_extension(path)
+}
+
+/// Returns whether an entry in the archive is one of the metadata files for a model, e.g.
+/// `attachments_000001.json` for `attachments`.
+pub fn is_metadata_filename(entry_path: &str, model_name: &str) -> bool {
+    entry_path.starts_with(&format!("{}_", model_name))
+        && entry_path.ends_with(".json")
+        && !entry_path.contains('/')
+}
+
+fn is_asset_entry_path(entry_path: &str) -> bool {
+    entry_path.starts_with(&format!("{}/", ATTACHMENTS_DIRECTORY_NAME))
+        || entry_path.starts_with(&format!("{}/", RELEASE_ASSETS_DIRECTORY_NAME))
+}
+
+// Entries in archives created with `tar -C <dir> .` are prefixed with `./`, which never appears
+// in `tarball://root/` asset URLs.
+fn normalize_entry_path(entry_path: &Path) -> String {
+    let entry_path = entry_path.to_string_lossy();
+    entry_path
+        .strip_prefix("./")
+        .unwrap_or(&entry_path)
+        .to_string()
+}
+
+fn sha256_hex(mut reader: impl Read) -> Result<String, Error> {
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+
+    loop {
+        let bytes_read = reader.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+// Reads all of the records in a single metadata file, returning none of them if the file is
+// invalid and we've been asked to skip invalid files
+fn read_metadata<T: DeserializeOwned>(
+    reader: impl Read,
+    file_name: &str,
+    options: &OpenOptions,
+) -> Result<Vec<T>, Error> {
+    eprintln!("Reading metadata file {}", file_name);
+
+    let mut records: Vec<T> = Vec::new();
+
+    match metadata::read_records(reader, file_name, |record| records.push(record)) {
+        Ok(()) => Ok(records),
+        Err(e) if options.skip_invalid_metadata => {
+            eprintln!("⚠️ {}. Skipping this file...", e);
+            Ok(Vec::new())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// Finds every `<model_name>_*.json` file (e.g. `attachments_000001.json`) in the working directory
+fn metadata_file_paths(working_directory: &Path, model_name: &str) -> Vec<PathBuf> {
+    glob(
+        working_directory
+            .join(format!("{}_*.json", model_name))
+            .to_str()
+            .unwrap(),
+    )
+    .unwrap()
+    .map(|entry| match entry {
+        Ok(path) => path,
+        Err(e) => panic!("Unexpected GlobError: {:?}", e),
+    })
+    .collect()
+}
+
+// Reads every `<model_name>_*.json` file (e.g. `attachments_000001.json`) in the working
+// directory, passing their records to `on_record` one at a time
+fn stream_metadata_files<T: DeserializeOwned>(
+    working_directory: &Path,
+    model_name: &str,
+    options: &OpenOptions,
+    mut on_record: impl FnMut(T),
+) -> Result<(), Error> {
+    for path in metadata_file_paths(working_directory, model_name) {
+        let file_name = path.display().to_string();
+        eprintln!("Reading metadata file {}", file_name);
+
+        // If we're skipping invalid files, we have to check the whole file is valid before we
+        // pass on any of its records
+        if options.skip_invalid_metadata {
+            if let Err(e) = metadata::read_records(File::open(&path)?, &file_name, |_: T| {}) {
+                eprintln!("⚠️ {}. Skipping this file...", e);
+                continue;
+            }
+        }
+
+        metadata::read_records(File::open(&path)?, &file_name, &mut on_record)?;
+    }
+
+    Ok(())
+}
+
+fn read_directory(
+    working_directory: &Path,
+    options: &OpenOptions,
+    mut on_attachment: impl FnMut(&Archive, Attachment),
+) -> Result<Archive, Error> {
+    let first_attachments_metadata_path =
+        working_directory.join(FIRST_ATTACHMENTS_METADATA_FILENAME);
+    let attachments_directory_path = working_directory.join(ATTACHMENTS_DIRECTORY_NAME);
+    let has_attachments =
+        first_attachments_metadata_path.exists() && attachments_directory_path.exists();
+    let has_release_assets = working_directory
+        .join(FIRST_RELEASES_METADATA_FILENAME)
+        .exists()
+        && working_directory
+            .join(RELEASE_ASSETS_DIRECTORY_NAME)
+            .exists();
+
+    if !has_attachments && !has_release_assets {
+        let error_mesage = format!("Could not find `{}` file and/or `{}/` directory. This suggests that either (a) your archive contains no attachments or (b) you're not in a directory created when you extract a GitHub archive.", first_attachments_metadata_path.display(), attachments_directory_path.display());
+        return Err(Error::other(error_mesage));
+    }
+
+    let archive = Archive::Directory(working_directory.to_path_buf());
+
+    // We only hold one record in memory at a time, since every file is already there to look up
+    if has_attachments {
+        eprintln!("📖 Reading attachments metadata files to find attachments...");
+
+        if let Err(e) =
+            stream_metadata_files(working_directory, "attachments", options, |attachment| {
+                on_attachment(&archive, attachment)
+            })
+        {
+            let error_mesage = format!("Could not read attachments metadata files: {}", e);
+            return Err(Error::other(error_mesage));
+        }
+    }
+
+    if has_release_assets {
+        eprintln!("📖 Reading releases metadata files to find release assets...");
+
+        if let Err(e) = stream_metadata_files(
+            working_directory,
+            "releases",
+            options,
+            |release: Release| {
+                for attachment in release.into_attachments() {
+                    on_attachment(&archive, attachment);
+                }
+            },
+        ) {
+            let error_mesage = format!("Could not read releases metadata files: {}", e);
+            return Err(Error::other(error_mesage));
+        }
+    }
+
+    Ok(archive)
+}
+
+// Opens a tarball for reading, decompressing it unless it's a plain `.tar` file
+fn open_tarball(path: &Path) -> Result<Box<dyn Read>, Error> {
+    let file = File::open(path)?;
+
+    if path.to_string_lossy().ends_with(".tar") {
+        Ok(Box::new(file))
+    } else {
+        Ok(Box::new(GzDecoder::new(file)))
+    }
+}
+
+fn read_tarball(
+    path: &Path,
+    options: &OpenOptions,
+    mut on_attachment: impl FnMut(&Archive, Attachment),
+) -> Result<Archive, Error> {
+    eprintln!(
+        "📦 Reading {} to find attachments, release assets and their metadata files...",
+        path.display()
+    );
+
+    // A truncated or corrupted download only shows up as an error part way through, so say which
+    // file it was in
+    let read_error = |e: Error| Error::other(format!("Could not read `{}`: {}", path.display(), e));
+
+    let reader = open_tarball(path).map_err(read_error)?;
+
+    let mut archive = Archive::Tarball {
+        path: path.to_path_buf(),
+        entry_sizes: HashMap::new(),
+        entry_hashes: HashMap::new(),
+        parent_records: options.collect_parent_records.then(ParentRecords::default),
+    };
+    // Attachments whose files we haven't come across yet, keyed by where their file lives in the
+    // archive. Each is numbered, so any whose files never turn up are passed on in the order they
+    // were listed.
+    let mut pending_attachments: HashMap<String, Vec<(usize, Attachment)>> = HashMap::new();
+    let mut listed_count = 0;
+    let mut found_metadata_file = false;
+
+    let mut tarball = tar::Archive::new(reader);
+
+    for entry in tarball.entries().map_err(read_error)? {
+        let mut entry = entry.map_err(read_error)?;
+        let entry_path = normalize_entry_path(&entry.path().map_err(read_error)?);
+
+        if let Archive::Tarball {
+            parent_records: Some(parent_records),
+            ..
+        } = &mut archive
+        {
+            if parent_records.read_metadata_file(&entry_path, &mut entry) {
+                continue;
+            }
+        }
+
+        let attachments: Vec<Attachment> = if is_metadata_filename(&entry_path, "attachments") {
+            found_metadata_file = true;
+            read_metadata(entry, &entry_path, options)?
+        } else if is_metadata_filename(&entry_path, "releases") {
+            found_metadata_file = true;
+            let releases: Vec<Release> = read_metadata(entry, &entry_path, options)?;
+            releases
+                .into_iter()
+                .flat_map(|release| release.into_attachments())
+                .collect()
+        } else if entry.header().entry_type().is_file() && is_asset_entry_path(&entry_path) {
+            let size = entry.header().size().map_err(read_error)?;
+            let sha256 = if options.hash_assets {
+                Some(sha256_hex(entry).map_err(read_error)?)
+            } else {
+                None
+            };
+            archive.record_entry(&entry_path, size, sha256);
+
+            if let Some(attachments) = pending_attachments.remove(&entry_path) {
+                for (_, attachment) in attachments {
+                    on_attachment(&archive, attachment);
+                }
+            }
+
+            continue;
+        } else {
+            continue;
+        };
+
+        for attachment in attachments {
+            listed_count += 1;
+
+            if archive.asset_size(&attachment.asset_url).is_some() {
+                on_attachment(&archive, attachment);
+            } else {
+                pending_attachments
+                    .entry(asset_entry_path(&attachment.asset_url))
+                    .or_default()
+                    .push((listed_count, attachment));
+            }
+        }
+    }
+
+    if !found_metadata_file {
+        let error_mesage = format!("Could not find any `attachments_*.json` or `releases_*.json` files in `{}`. This suggests that either (a) your archive contains no attachments or (b) this isn't a GitHub migration archive.", path.display());
+        return Err(Error::other(error_mesage));
+    }
+
+    let mut missing_attachments: Vec<(usize, Attachment)> =
+        pending_attachments.into_values().flatten().collect();
+    missing_attachments.sort_by_key(|(listed_index, _)| *listed_index);
+
+    for (_, attachment) in missing_attachments {
+        on_attachment(&archive, attachment);
+    }
+
+    Ok(archive)
+}
@@ -0,0 +1,175 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use crate::summary::{Group, GroupBy, Grouper};
+use crate::SizedAttachment;
+
+// An attachment ranked by its size. Attachments with the same size are ranked by the order we
+// read them in, with later attachments ranked higher.
+struct RankedAttachment {
+    index: usize,
+    sized_attachment: SizedAttachment,
+}
+
+impl RankedAttachment {
+    fn rank(&self) -> (u64, usize) {
+        (self.sized_attachment.size, self.index)
+    }
+}
+
+impl PartialEq for RankedAttachment {
+    fn eq(&self, other: &Self) -> bool {
+        self.rank() == other.rank()
+    }
+}
+
+impl Eq for RankedAttachment {}
+
+impl PartialOrd for RankedAttachment {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RankedAttachment {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rank().cmp(&other.rank())
+    }
+}
+
+/// Collects attachments one at a time as they're read from an archive, only keeping what we need
+/// to print the results:
+///
+/// * attachments smaller than `min_size` are counted, then dropped
+/// * with `top`, only the largest attachments so far are kept, in a heap
+/// * when grouping without `top`, attachments are added to their group's totals, then dropped
+pub struct Collector {
+    min_size: u64,
+    top: Option<usize>,
+    group_by: Option<GroupBy>,
+    grouper: Option<Grouper>,
+    // A min-heap, so the smallest kept attachment is the first to go when we're over `top`
+    kept_attachments: BinaryHeap<Reverse<RankedAttachment>>,
+    next_index: usize,
+    hidden_count: usize,
+    hidden_size: u64,
+    total_size: u64,
+    largest_attachment: Option<(String, u64)>,
+}
+
+/// What's left once all of the attachments have been collected.
+pub struct Collected {
+    /// The attachments to show, largest first. Empty if attachments were grouped as they were
+    /// collected.
+    pub attachments_by_size: Vec<SizedAttachment>,
+    /// The attachments' groups, largest first, if we were asked to group them
+    pub groups: Option<Vec<Group>>,
+    /// The number of attachments hidden by `min_size` or `top`
+    pub hidden_count: usize,
+    /// The total size of the attachments hidden by `min_size` or `top`
+    pub hidden_size: u64,
+    /// The total size of every attachment, including hidden ones
+    pub total_size: u64,
+    /// The name and size of the largest attachment, including hidden ones
+    pub largest_attachment: Option<(String, u64)>,
+}
+
+impl Collector {
+    pub fn new(min_size: Option<u64>, top: Option<usize>, group_by: Option<GroupBy>) -> Collector {
+        // If we need to find the top attachments first, we can't group them until the end
+        let grouper = match (&group_by, top) {
+            (Some(group_by), None) => Some(Grouper::new(group_by.clone())),
+            _ => None,
+        };
+
+        Collector {
+            min_size: min_size.unwrap_or(0),
+            top,
+            group_by,
+            grouper,
+            kept_attachments: BinaryHeap::new(),
+            next_index: 0,
+            hidden_count: 0,
+            hidden_size: 0,
+            total_size: 0,
+            largest_attachment: None,
+        }
+    }
+
+    pub fn add(&mut self, sized_attachment: SizedAttachment) {
+        let index = self.next_index;
+        self.next_index += 1;
+
+        // Keep track of these before anything is hidden, so they cover every attachment
+        self.total_size += sized_attachment.size;
+        if self
+            .largest_attachment
+            .as_ref()
+            .is_none_or(|(_, largest_size)| sized_attachment.size > *largest_size)
+        {
+            self.largest_attachment = Some((
+                sized_attachment.attachment.asset_name.clone(),
+                sized_attachment.size,
+            ));
+        }
+
+        if sized_attachment.size < self.min_size {
+            self.hide(&sized_attachment);
+            return;
+        }
+
+        if let Some(grouper) = &mut self.grouper {
+            grouper.add(&sized_attachment);
+            return;
+        }
+
+        self.kept_attachments.push(Reverse(RankedAttachment {
+            index,
+            sized_attachment,
+        }));
+
+        if let Some(top) = self.top {
+            if self.kept_attachments.len() > top {
+                if let Some(Reverse(smallest)) = self.kept_attachments.pop() {
+                    self.hide(&smallest.sized_attachment);
+                }
+            }
+        }
+    }
+
+    pub fn finish(self) -> Collected {
+        // Sorting a min-heap of `Reverse`s gives us the largest attachments first
+        let attachments_by_size: Vec<SizedAttachment> = self
+            .kept_attachments
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(ranked_attachment)| ranked_attachment.sized_attachment)
+            .collect();
+
+        let groups = match (self.grouper, &self.group_by) {
+            (Some(grouper), _) => Some(grouper.into_groups()),
+            (None, Some(group_by)) => {
+                let mut grouper = Grouper::new(group_by.clone());
+                attachments_by_size
+                    .iter()
+                    .for_each(|sized_attachment| grouper.add(sized_attachment));
+                Some(grouper.into_groups())
+            }
+            (None, None) => None,
+        };
+
+        Collected {
+            attachments_by_size,
+            groups,
+            hidden_count: self.hidden_count,
+            hidden_size: self.hidden_size,
+            total_size: self.total_size,
+            largest_attachment: self.largest_attachment,
+        }
+    }
+
+    fn hide(&mut self, sized_attachment: &SizedAttachment) {
+        self.hidden_count += 1;
+        self.hidden_size += sized_attachment.size;
+    }
+}
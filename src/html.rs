@@ -0,0 +1,218 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::collections::{HashMap, HashSet};
+
+use crate::output::format_size;
+use crate::summary::{GroupBy, Grouper};
+use crate::SizedAttachment;
+
+/// Images bigger than this aren't embedded in the HTML report, so it stays a reasonable size.
+pub const MAX_THUMBNAIL_SIZE: u64 = 1024 * 1024;
+
+// The styles and scripts for the report. Any table with the `sortable` class can be sorted by
+// clicking its headings (using each cell's `data-sort` value, if it has one), and any input with
+// a `data-filter` attribute hides the rows in that table which don't contain what's typed.
+const HEAD: &str = r#"<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<style>
+  body { font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Helvetica, Arial, sans-serif; margin: 2em; color: #1f2328; }
+  table { border-collapse: collapse; width: 100%; margin-bottom: 2em; }
+  th, td { border-bottom: 1px solid #d0d7de; padding: 6px 12px; text-align: left; vertical-align: middle; }
+  th { background: #f6f8fa; }
+  table.sortable th { cursor: pointer; user-select: none; }
+  table.sortable th[aria-sort="ascending"]::after { content: " ▲"; }
+  table.sortable th[aria-sort="descending"]::after { content: " ▼"; }
+  td.size { text-align: right; white-space: nowrap; }
+  img.thumbnail { max-width: 96px; max-height: 96px; }
+  input[data-filter] { padding: 6px; width: 24em; margin-bottom: 1em; }
+  .state { color: #59636e; }
+</style>
+<script>
+  document.addEventListener("DOMContentLoaded", () => {
+    document.querySelectorAll("table.sortable th").forEach((heading) => {
+      heading.addEventListener("click", () => {
+        const table = heading.closest("table");
+        const body = table.tBodies[0];
+        const column = heading.cellIndex;
+        const ascending = heading.getAttribute("aria-sort") !== "ascending";
+        const value = (row) => {
+          const cell = row.cells[column];
+          return cell.dataset.sort !== undefined ? cell.dataset.sort : cell.textContent.trim();
+        };
+
+        const rows = Array.from(body.rows).sort((a, b) => {
+          const [x, y] = [value(a), value(b)];
+          const compared = x !== "" && y !== "" && !isNaN(x) && !isNaN(y)
+            ? Number(x) - Number(y)
+            : x.localeCompare(y);
+          return ascending ? compared : -compared;
+        });
+
+        table.querySelectorAll("th").forEach((other) => other.removeAttribute("aria-sort"));
+        heading.setAttribute("aria-sort", ascending ? "ascending" : "descending");
+        rows.forEach((row) => body.appendChild(row));
+      });
+    });
+
+    document.querySelectorAll("input[data-filter]").forEach((input) => {
+      input.addEventListener("input", () => {
+        const query = input.value.toLowerCase();
+        document.querySelectorAll(`#${input.dataset.filter} tbody tr`).forEach((row) => {
+          row.hidden = !row.textContent.toLowerCase().includes(query);
+        });
+      });
+    });
+  });
+</script>"#;
+
+// Escapes text so it can be safely included in HTML, including inside attribute values
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Returns the asset URLs of the attachments which should have a thumbnail in the HTML report:
+/// images no bigger than `MAX_THUMBNAIL_SIZE`.
+pub fn thumbnail_asset_urls(attachments_by_size: &[SizedAttachment]) -> HashSet<&str> {
+    attachments_by_size
+        .iter()
+        .filter(|sized_attachment| {
+            sized_attachment
+                .attachment
+                .asset_content_type
+                .to_lowercase()
+                .starts_with("image/")
+                && sized_attachment.size <= MAX_THUMBNAIL_SIZE
+        })
+        .map(|sized_attachment| sized_attachment.attachment.asset_url.as_str())
+        .collect()
+}
+
+// Formats the table totalling up the attachments in each repository
+fn format_repositories_table(attachments_by_size: &[SizedAttachment]) -> String {
+    let mut grouper = Grouper::new(GroupBy::Repository);
+    for sized_attachment in attachments_by_size {
+        grouper.add(sized_attachment);
+    }
+
+    let mut rows = String::new();
+    for group in grouper.into_groups() {
+        rows.push_str(&format!(
+            "<tr><td>{}</td><td data-sort=\"{}\">{}</td><td class=\"size\" data-sort=\"{}\">{}</td></tr>\n",
+            escape_html(&group.name),
+            group.count,
+            group.count,
+            group.size,
+            format_size(group.size)
+        ));
+    }
+
+    format!(
+        "<table class=\"sortable\" id=\"repositories\">\n<thead><tr><th>Repository</th><th>Attachments</th><th>Total size</th></tr></thead>\n<tbody>\n{}</tbody>\n</table>",
+        rows
+    )
+}
+
+// Formats the cell linking to the issue, pull request, comment, review or release an attachment
+// belongs to, with its title and state if we know them
+fn format_parent_cell(sized_attachment: &SizedAttachment) -> String {
+    let attachment = &sized_attachment.attachment;
+    let parent = sized_attachment.parent.as_ref();
+
+    let Some(parent_url) = attachment.parent_url() else {
+        return "<td>Unknown</td>".to_string();
+    };
+
+    let mut description = match parent.and_then(|parent| parent.comment_kind) {
+        Some(comment_kind) => format!("{} on ", comment_kind),
+        None => String::new(),
+    };
+    match (
+        parent.and_then(|parent| parent.number),
+        parent.and_then(|parent| parent.title.as_deref()),
+    ) {
+        (Some(number), Some(title)) => description.push_str(&format!("#{} {}", number, title)),
+        (Some(number), None) => description.push_str(&format!("#{}", number)),
+        _ => description.push_str(parent_url),
+    }
+
+    let state = match parent.and_then(|parent| parent.state.as_ref()) {
+        Some(state) => format!(" <span class=\"state\">({})</span>", state.name()),
+        None => String::new(),
+    };
+
+    format!(
+        "<td><a href=\"{}\">{}</a>{}</td>",
+        escape_html(parent_url),
+        escape_html(&description),
+        state
+    )
+}
+
+// Formats the table listing every attachment
+fn format_attachments_table(
+    attachments_by_size: &[SizedAttachment],
+    thumbnails: &HashMap<String, Vec<u8>>,
+) -> String {
+    let mut rows = String::new();
+
+    for sized_attachment in attachments_by_size {
+        let attachment = &sized_attachment.attachment;
+
+        let thumbnail = match thumbnails.get(&attachment.asset_url) {
+            Some(contents) => format!(
+                "<img class=\"thumbnail\" alt=\"\" src=\"data:{};base64,{}\">",
+                escape_html(&attachment.asset_content_type),
+                STANDARD.encode(contents)
+            ),
+            None => String::new(),
+        };
+
+        rows.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td class=\"size\" data-sort=\"{}\">{}</td><td>{}</td><td>{}</td>{}<td>{}</td><td>{}</td></tr>\n",
+            thumbnail,
+            escape_html(&attachment.asset_name),
+            sized_attachment.size,
+            format_size(sized_attachment.size),
+            escape_html(&attachment.asset_content_type),
+            escape_html(&attachment.repository().unwrap_or_default()),
+            format_parent_cell(sized_attachment),
+            escape_html(attachment.user_login().unwrap_or_default()),
+            escape_html(&attachment.created_at)
+        ));
+    }
+
+    format!(
+        "<table class=\"sortable\" id=\"attachments\">\n<thead><tr><th>Preview</th><th>Name</th><th>Size</th><th>Content type</th><th>Repository</th><th>Parent</th><th>Uploaded by</th><th>Created</th></tr></thead>\n<tbody>\n{}</tbody>\n</table>",
+        rows
+    )
+}
+
+/// Formats the attachments as a self-contained HTML page, returned as a single message. The page
+/// has a sortable table totalling up each repository's attachments, followed by a sortable,
+/// filterable table of the attachments themselves, with thumbnails for the images in `thumbnails`
+/// (keyed by asset URL).
+pub fn format_as_html(
+    attachments_by_size: &[SizedAttachment],
+    thumbnails: &HashMap<String, Vec<u8>>,
+    archive_name: &str,
+) -> Vec<String> {
+    let total_size: u64 = attachments_by_size
+        .iter()
+        .map(|sized_attachment| sized_attachment.size)
+        .sum();
+
+    vec![format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<title>Attachments in {}</title>\n{}\n</head>\n<body>\n<h1>Attachments in {}</h1>\n<p>{} attachment(s) totalling {}</p>\n<h2>Repositories</h2>\n{}\n<h2>Attachments</h2>\n<input type=\"search\" placeholder=\"Filter attachments...\" data-filter=\"attachments\">\n{}\n</body>\n</html>",
+        escape_html(archive_name),
+        HEAD,
+        escape_html(archive_name),
+        attachments_by_size.len(),
+        format_size(total_size),
+        format_repositories_table(attachments_by_size),
+        format_attachments_table(attachments_by_size, thumbnails)
+    )]
+}
@@ -1,11 +1,192 @@
-use byte_unit::{Byte, UnitType};
-use exitcode;
-use glob::glob;
+mod archive;
+mod collector;
+mod html;
+mod metadata;
+mod output;
+mod parents;
+mod prune;
+mod remediate;
+mod slim;
+mod sqlite;
+mod summary;
+
+use archive::{Archive, OpenOptions};
+use byte_unit::Byte;
+use clap::Parser;
+use collector::{Collected, Collector};
+use output::OutputFormat;
+use remediate::RemediationFormat;
 use serde_derive::{Deserialize, Serialize};
-use std::fs;
-use std::io::{Error, ErrorKind};
-use std::path::Path;
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use summary::GroupBy;
+
+/// Identify large attachments and release assets in GitHub migration archives, so you can clean
+/// them up and reduce the size of your archives
+#[derive(Parser, Debug)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// The migration archive to analyze - either a `.tar.gz` or `.tar` file, or the directory
+    /// created when you extract one. Defaults to the current directory.
+    archive: Option<String>,
+
+    /// How to print the results
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Only show attachments at least this big, e.g. `10MiB` or `500KB`
+    #[arg(long, value_parser = parse_size)]
+    min_size: Option<u64>,
+
+    /// Only show this many of the largest attachments
+    #[arg(long)]
+    top: Option<usize>,
+
+    /// Instead of listing attachments individually, show the number and total size of
+    /// attachments in each group
+    #[arg(long, value_enum)]
+    group_by: Option<GroupBy>,
+
+    /// Instead of listing attachments individually, hash each attachment's file and show sets of
+    /// attachments with identical contents
+    #[arg(long, conflicts_with = "group_by")]
+    find_duplicates: bool,
+
+    /// Instead of listing attachments, list files in the archive's `attachments/` and
+    /// `release_assets/` directories which aren't used by any attachment or release asset
+    #[arg(long, conflicts_with_all = ["group_by", "find_duplicates"])]
+    find_orphans: bool,
+
+    /// Exit with a non-zero exit code (66) if any attachment files listed in the archive's
+    /// metadata are missing. By default, missing files are reported as warnings and skipped.
+    #[arg(long)]
+    strict: bool,
+
+    /// Exit with a non-zero exit code (3) if all of the attachments together are bigger than
+    /// this, e.g. `5GiB`. Attachments hidden by --min-size and --top still count. If files are
+    /// missing and --strict is set, the exit code is 66 instead.
+    #[arg(long, value_parser = parse_size)]
+    fail_if_total_over: Option<u64>,
+
+    /// Exit with a non-zero exit code (3) if any attachment is bigger than this, e.g. `100MiB`.
+    /// If files are missing and --strict is set, the exit code is 66 instead.
+    #[arg(long, value_parser = parse_size)]
+    fail_if_any_over: Option<u64>,
+
+    /// Skip metadata files (e.g. `attachments_000001.json`) that can't be parsed with a warning,
+    /// rather than stopping with an error
+    #[arg(long)]
+    skip_invalid_metadata: bool,
+
+    /// How many attachments to size (and hash, with --find-duplicates) at once. Raising this can
+    /// speed things up when your archive is on network storage. Defaults to the number of CPUs.
+    #[arg(long)]
+    jobs: Option<NonZeroUsize>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Replace links to large attachments in the archive's issues, pull requests, comments,
+    /// reviews and releases with a placeholder, writing modified copies of their metadata files
+    Prune(PruneArgs),
+    /// Write a copy of the archive without the files for large attachments, dropping them from
+    /// the `attachments_*.json` metadata files. Metadata files skipped with
+    /// --skip-invalid-metadata are copied into it unchanged.
+    Slim(SlimArgs),
+    /// Write a script (or JSON batch file) that removes links to large attachments from the
+    /// issues, pull requests, comments and reviews on GitHub itself
+    Remediate(RemediateArgs),
+    /// Write every attachment, with its metadata, size, hash and the issue or pull request it
+    /// belongs to, to a SQLite database you can query
+    Export(ExportArgs),
+}
+
+/// The arguments every subcommand uses to read the archive.
+#[derive(clap::Args, Debug)]
+struct CommonArgs {
+    /// The migration archive to read - either a `.tar.gz` or `.tar` file, or the directory created
+    /// when you extract one. Defaults to the current directory.
+    archive: Option<String>,
+
+    /// Skip metadata files (e.g. `attachments_000001.json`) that can't be parsed with a warning,
+    /// rather than stopping with an error
+    #[arg(long)]
+    skip_invalid_metadata: bool,
+
+    /// How many attachments to size (and, for `export`, hash) at once. Defaults to the number of
+    /// CPUs.
+    #[arg(long)]
+    jobs: Option<NonZeroUsize>,
+}
+
+#[derive(clap::Args, Debug)]
+struct PruneArgs {
+    /// Replace links to attachments at least this big, e.g. `10MiB` or `500KB`
+    #[arg(long, value_parser = parse_size)]
+    min_size: u64,
+
+    /// What to replace each attachment's URL with
+    #[arg(long, default_value = prune::DEFAULT_PLACEHOLDER)]
+    placeholder: String,
+
+    /// The directory to write the modified metadata files to. Metadata files without any links
+    /// to replace aren't written.
+    #[arg(long)]
+    output_directory: PathBuf,
+
+    #[command(flatten)]
+    common: CommonArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct SlimArgs {
+    /// Leave out attachments at least this big, e.g. `10MiB` or `500KB`
+    #[arg(long, value_parser = parse_size)]
+    min_size: u64,
+
+    /// Where to write the slimmed-down `.tar.gz` archive
+    #[arg(long)]
+    output: PathBuf,
+
+    #[command(flatten)]
+    common: CommonArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct RemediateArgs {
+    /// Remove links to attachments at least this big, e.g. `10MiB` or `500KB`
+    #[arg(long, value_parser = parse_size)]
+    min_size: u64,
+
+    /// Whether to write a Bash script using `gh api`, or a JSON array of API requests
+    #[arg(long, value_enum, default_value_t = RemediationFormat::Script)]
+    format: RemediationFormat,
+
+    #[command(flatten)]
+    common: CommonArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ExportArgs {
+    /// Where to write the SQLite database. Any existing file there is replaced.
+    #[arg(long)]
+    output: PathBuf,
+
+    #[command(flatten)]
+    common: CommonArgs,
+}
+
+// Parses a human-readable size like `10MiB` into a number of bytes
+fn parse_size(size: &str) -> Result<u64, String> {
+    match Byte::parse_str(size, true) {
+        Ok(byte) => Ok(byte.as_u64()),
+        Err(e) => Err(format!("`{}` isn't a valid size: {}", size, e)),
+    }
+}
 
 #[derive(Deserialize, Serialize, Debug)]
 struct Attachment {
@@ -14,6 +195,13 @@ struct Attachment {
     pull_request: Option<String>,
     issue: Option<String>,
     issue_comment: Option<String>,
+    // Pull request reviews, review comments and commit comments can have attachments too
+    pull_request_review: Option<String>,
+    pull_request_review_comment: Option<String>,
+    commit_comment: Option<String>,
+    // Set for attachments in release notes, and for release assets, which we treat as attachments
+    // on their release
+    release: Option<String>,
     user: Option<String>,
     asset_name: String,
     asset_content_type: String,
@@ -21,136 +209,653 @@ struct Attachment {
     created_at: String,
 }
 
-const FIRST_ATTACHMENTS_METADATA_FILENAME: &str = "attachments_000001.json";
-const ATTACHMENTS_DIRECTORY_NAME: &str = "attachments";
-
-fn read_attachments_file(path: PathBuf) -> Result<Vec<Attachment>, std::io::Error> {
-    eprintln!("Reading attachment file {}", path.display());
-    let attachments_json = std::fs::read_to_string(&path)?;
-    let attachments: Vec<Attachment> = serde_json::from_str(&attachments_json).unwrap();
+#[derive(Deserialize, Serialize, Debug)]
+struct Release {
+    url: String,
+    #[serde(default)]
+    release_assets: Vec<ReleaseAsset>,
+}
 
-    Ok(attachments)
+#[derive(Deserialize, Serialize, Debug)]
+struct ReleaseAsset {
+    r#type: String,
+    url: String,
+    user: Option<String>,
+    name: String,
+    content_type: String,
+    asset_url: String,
+    created_at: String,
 }
 
-fn read_attachments_files(working_directory: &PathBuf) -> Result<Vec<Attachment>, std::io::Error> {
-    let mut attachments: Vec<Attachment> = Vec::new();
+impl Attachment {
+    /// Returns the URL of the pull request, issue, comment, review or release the attachment
+    /// belongs to, if we know it.
+    fn parent_url(&self) -> Option<&str> {
+        self.pull_request
+            .as_deref()
+            .or(self.issue.as_deref())
+            .or(self.issue_comment.as_deref())
+            .or(self.pull_request_review_comment.as_deref())
+            .or(self.pull_request_review.as_deref())
+            .or(self.commit_comment.as_deref())
+            .or(self.release.as_deref())
+    }
 
-    for entry in glob(
-        &working_directory
-            .join("attachments_*.json")
-            .to_str()
-            .unwrap(),
-    )
-    .unwrap()
-    {
-        match entry {
-            Ok(path) => {
-                let mut file_attachments = read_attachments_file(path)?;
-                attachments.append(&mut file_attachments);
+    /// Returns whether this is one of a release's assets, rather than an attachment in some
+    /// Markdown.
+    fn is_release_asset(&self) -> bool {
+        self.r#type == "release_asset"
+    }
+
+    /// Returns the login of the user who uploaded the attachment, based on their profile URL (e.g.
+    /// `https://github.com/dependabot[bot]`).
+    fn user_login(&self) -> Option<&str> {
+        login_from_user_url(self.user.as_deref()?)
+    }
+
+    /// Returns the `owner/repo` that the attachment belongs to, based on its parent URL (e.g.
+    /// `https://github.com/owner/repo/pull/1`).
+    fn repository(&self) -> Option<String> {
+        let parent_url = self.parent_url()?;
+        let path = parent_url.split_once("://")?.1;
+        let mut path_segments = path.split('/').skip(1);
+
+        match (path_segments.next(), path_segments.next()) {
+            (Some(owner), Some(repo)) if !owner.is_empty() && !repo.is_empty() => {
+                Some(format!("{}/{}", owner, repo))
             }
-            Err(e) => panic!("Unexpected GlobError: {:?}", e),
+            _ => None,
         }
     }
+}
+
+/// Returns the login from a user's profile URL, e.g. `octocat` for `https://github.com/octocat`.
+fn login_from_user_url(user: &str) -> Option<&str> {
+    match user.trim_end_matches('/').rsplit_once('/') {
+        Some((_, login)) if !login.is_empty() => Some(login),
+        _ => None,
+    }
+}
 
-    Ok(attachments)
+impl Release {
+    /// Turns the release's assets into `Attachment`s linked to the release, so they can be sized
+    /// and reported alongside issue and pull request attachments.
+    fn into_attachments(self) -> Vec<Attachment> {
+        let release_url = self.url;
+
+        self.release_assets
+            .into_iter()
+            .map(|release_asset| Attachment {
+                r#type: release_asset.r#type,
+                url: release_asset.url,
+                pull_request: None,
+                issue: None,
+                issue_comment: None,
+                pull_request_review: None,
+                pull_request_review_comment: None,
+                commit_comment: None,
+                release: Some(release_url.clone()),
+                user: release_asset.user,
+                asset_name: release_asset.name,
+                asset_content_type: release_asset.content_type,
+                asset_url: release_asset.asset_url,
+                created_at: release_asset.created_at,
+            })
+            .collect()
+    }
 }
 
 fn get_working_directory(working_directory_path: Option<String>) -> PathBuf {
-    if working_directory_path.is_none() {
-        return PathBuf::from(".");
-    } else {
-        return PathBuf::from(working_directory_path.unwrap());
+    match working_directory_path {
+        Some(working_directory_path) => PathBuf::from(working_directory_path),
+        None => PathBuf::from("."),
     }
 }
 
-fn process_attachments(
-    provided_working_directory: Option<String>,
-) -> Result<Vec<String>, std::io::Error> {
-    let working_directory = get_working_directory(provided_working_directory);
+// How many attachments we read before sizing them in parallel
+const ATTACHMENTS_BATCH_SIZE: usize = 1000;
 
-    let first_attachments_metadata_path =
-        working_directory.join(FIRST_ATTACHMENTS_METADATA_FILENAME);
-    let attachments_directory_path = working_directory.join(ATTACHMENTS_DIRECTORY_NAME);
+/// An attachment, alongside where its file lives in the archive, the file's size in bytes and,
+/// if we're looking for duplicates, the file's SHA-256 hash. For attachments in comments, once
+/// we've looked it up, we also know the issue or pull request the comment is on.
+#[derive(Debug)]
+struct SizedAttachment {
+    attachment: Attachment,
+    path: String,
+    size: u64,
+    sha256: Option<String>,
+    parent: Option<parents::Parent>,
+}
 
-    if !first_attachments_metadata_path.exists() || !attachments_directory_path.exists() {
-        let error_mesage = format!("Could not find `{}` file and/or `{}/` directory. This suggests that either (a) your archive contains no attachments or (b) you're not in a directory created when you extract a GitHub archive.", first_attachments_metadata_path.display(), attachments_directory_path.display());
-        return Err(Error::new(ErrorKind::Other, error_mesage));
-    }
+/// An attachment whose file we couldn't find in the archive, alongside where we expected it to be.
+/// When exporting, we also look up the issue or pull request it belongs to.
+#[derive(Debug)]
+struct MissingAttachment {
+    attachment: Attachment,
+    path: String,
+    parent: Option<parents::Parent>,
+}
 
-    eprintln!("📖 Reading attachments metadata files to find attachments...");
+/// The results of processing an archive: the messages to print, plus anything else `main` needs to
+/// decide how to exit.
+#[derive(Debug)]
+struct Results {
+    messages: Vec<String>,
+    missing_attachments: Vec<MissingAttachment>,
+    /// A message for each of the --fail-if-total-over and --fail-if-any-over limits that the
+    /// attachments went over
+    breached_limits: Vec<String>,
+}
 
-    let attachments: Vec<Attachment> = match read_attachments_files(&working_directory) {
-        Ok(attachments) => attachments,
-        Err(e) => {
-            let error_mesage = format!("Could not read attachments metadata files: {}", e);
-            return Err(Error::new(ErrorKind::Other, error_mesage));
+/// The exit code used when the attachments go over the --fail-if-total-over or --fail-if-any-over
+/// limits, so scripts can tell that apart from errors and missing files.
+const LIMIT_BREACHED_EXIT_CODE: exitcode::ExitCode = 3;
+
+// What we found when we looked for an attachment's file in the archive
+enum SizingOutcome {
+    Sized(SizedAttachment),
+    Missing(MissingAttachment),
+}
+
+// Looks up the size (and, if asked, the hash) of an attachment's file in the archive
+fn size_attachment(archive: &Archive, attachment: Attachment, hash: bool) -> SizingOutcome {
+    let path = archive.asset_path(&attachment.asset_url);
+    let size = match archive.asset_size(&attachment.asset_url) {
+        Some(size) => size,
+        None => {
+            return SizingOutcome::Missing(MissingAttachment {
+                attachment,
+                path,
+                parent: None,
+            })
         }
     };
 
-    let attachments_count = attachments.len();
-    eprintln!("🔎 Found {} attachment(s)", attachments_count);
+    let sha256 = if hash {
+        archive.asset_hash(&attachment.asset_url)
+    } else {
+        None
+    };
 
-    let mut attachments_by_size: Vec<(&Attachment, u128)> = attachments
-        .iter()
-        .enumerate()
-        .map(|(index, attachment)| {
-            eprintln!(
-                "📜 Processing attachment {}/{}",
-                index + 1,
-                attachments_count
-            );
+    SizingOutcome::Sized(SizedAttachment {
+        attachment,
+        path,
+        size,
+        sha256,
+        parent: None,
+    })
+}
+
+// Sizes (and, if asked, hashes) a batch of attachments, splitting the work between `jobs` threads,
+// then passes them to the collector in the same order they were read
+fn process_batch(
+    batch: Vec<Attachment>,
+    archive: &Archive,
+    hash: bool,
+    jobs: usize,
+    collector: &mut Collector,
+    missing_attachments: &mut Vec<MissingAttachment>,
+) {
+    if batch.is_empty() {
+        return;
+    }
 
-            let relative_path = Path::new(&working_directory).join(attachment.asset_url.replace("tarball://root/", ""));
+    let chunk_size = batch.len().div_ceil(jobs);
+    let mut chunks: Vec<Vec<Attachment>> = Vec::new();
+    let mut batch = batch.into_iter().peekable();
 
-            if !relative_path.exists() {
-                panic!("Could not find listed attachment file `{}`. Please make sure you're running this tool in the directory created when you extract a GitHub archive.", relative_path.display());
+    while batch.peek().is_some() {
+        chunks.push(batch.by_ref().take(chunk_size).collect());
+    }
+
+    let outcomes: Vec<SizingOutcome> = std::thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .into_iter()
+                        .map(|attachment| size_attachment(archive, attachment, hash))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    for outcome in outcomes {
+        match outcome {
+            SizingOutcome::Sized(sized_attachment) => collector.add(sized_attachment),
+            SizingOutcome::Missing(missing_attachment) => {
+                missing_attachments.push(missing_attachment)
             }
+        }
+    }
+}
+
+/// The attachments read from an archive, once they've all been sized and collected.
+struct Collection {
+    archive: Archive,
+    collected: Collected,
+    missing_attachments: Vec<MissingAttachment>,
+    // The paths of every attachment's file, if we were asked to keep track of them
+    referenced_paths: HashSet<String>,
+}
+
+fn get_jobs(jobs: Option<NonZeroUsize>) -> usize {
+    match jobs {
+        Some(jobs) => jobs.get(),
+        None => std::thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+    }
+}
+
+// Reads the attachments from the archive at `working_directory`, sizing (and, if asked, hashing)
+// them and handing them to the collector
+fn collect_attachments(
+    working_directory: &Path,
+    open_options: &OpenOptions,
+    mut collector: Collector,
+    jobs: usize,
+    track_paths: bool,
+) -> Result<Collection, std::io::Error> {
+    // Attachments are handed to the collector as they're read, so we never need to hold every
+    // attachment in memory at once (unless every attachment is going to be in the results). We
+    // batch them up first so we can size and hash each batch in parallel.
+    let mut missing_attachments: Vec<MissingAttachment> = Vec::new();
+    let mut attachments_count = 0;
+    let mut batch: Vec<Attachment> = Vec::new();
+    let mut referenced_paths: HashSet<String> = HashSet::new();
+
+    let archive = Archive::open(working_directory, open_options, |archive, attachment| {
+        attachments_count += 1;
+        eprintln!("📜 Processing attachment {}", attachments_count);
+
+        if track_paths {
+            referenced_paths.insert(archive.asset_path(&attachment.asset_url));
+        }
+
+        batch.push(attachment);
+
+        if batch.len() == ATTACHMENTS_BATCH_SIZE {
+            process_batch(
+                std::mem::take(&mut batch),
+                archive,
+                open_options.hash_assets,
+                jobs,
+                &mut collector,
+                &mut missing_attachments,
+            );
+        }
+    })?;
+
+    process_batch(
+        batch,
+        &archive,
+        open_options.hash_assets,
+        jobs,
+        &mut collector,
+        &mut missing_attachments,
+    );
+
+    eprintln!("🔎 Found {} attachment(s)", attachments_count);
 
-            let size = fs::metadata(&relative_path).unwrap().len() as u128;
-            return (attachment, size);
-        })
-        .collect::<Vec<(&Attachment, u128)>>();
+    for missing_attachment in missing_attachments.iter() {
+        eprintln!(
+            "⚠️ Could not find file `{}` for attachment {} ({}). Skipping...",
+            missing_attachment.path,
+            missing_attachment.attachment.asset_name,
+            missing_attachment
+                .attachment
+                .parent_url()
+                .unwrap_or("unknown parent")
+        );
+    }
+
+    if !missing_attachments.is_empty() {
+        eprintln!(
+            "⚠️ {} attachment file(s) listed in the metadata files were missing from `{}`. Please make sure you're running this tool on a GitHub archive, or the directory created when you extract one.",
+            missing_attachments.len(),
+            archive.describe()
+        );
+    }
 
     eprintln!("🪣  Sorting attachments by size...");
 
-    // Sort the attachments by size, largest first. This is done in memory. I haven't figured out how
-    // to do an immutable sort yet.
-    attachments_by_size.sort_unstable_by_key(|attachment_and_size| attachment_and_size.1);
-    attachments_by_size.reverse();
-
-    // Accumulate the messages to print. We do this instead of directly looping and printing messages as
-    // we go becuase it allows us to print warning messages first, before the actual results.
-    let messages: Vec<String> = attachments_by_size.iter().fold(Vec::new(), |mut messages, (attachment, size)| {
-        let byte = Byte::from_u128(*size).unwrap();
-        let adjusted_byte = byte.get_appropriate_unit(UnitType::Binary);
-        let size_as_string = format!("{adjusted_byte:#.0}");
-
-        if attachment.pull_request.is_some()
-        {
-            messages.push(format!("{} ({}) - {}", attachment.asset_name, &attachment.pull_request.clone().unwrap(), size_as_string));
-        } else if attachment.issue.is_some() {
-            messages.push(format!("{} ({}) - {}", attachment.asset_name, &attachment.issue.clone().unwrap(), size_as_string));
-        } else if attachment.issue_comment.is_some() {
-            messages.push(format!("{} ({}) - {}", attachment.asset_name, &attachment.issue_comment.clone().unwrap(), size_as_string));
-        } else {
-            eprintln!("⚠️ Could not find issue, pull request or issue comment for attachment {}. Skipping...", attachment.asset_name);
-        }
-
-        return messages;
-    });
+    Ok(Collection {
+        archive,
+        collected: collector.finish(),
+        missing_attachments,
+        referenced_paths,
+    })
+}
+
+// Reads the archive a subcommand was given, sizing (and, if asked, hashing) the attachments at
+// least `min_size` big
+fn collect_subcommand_attachments(
+    args: &CommonArgs,
+    min_size: Option<u64>,
+    hash_assets: bool,
+    collect_parent_records: bool,
+) -> Result<Collection, std::io::Error> {
+    let open_options = OpenOptions {
+        hash_assets,
+        skip_invalid_metadata: args.skip_invalid_metadata,
+        collect_parent_records,
+    };
+
+    collect_attachments(
+        &get_working_directory(args.archive.clone()),
+        &open_options,
+        Collector::new(min_size, None, None),
+        get_jobs(args.jobs),
+        false,
+    )
+}
+
+fn prune_attachments(args: &PruneArgs) -> Result<Results, std::io::Error> {
+    let Collection {
+        archive,
+        collected,
+        missing_attachments,
+        ..
+    } = collect_subcommand_attachments(&args.common, Some(args.min_size), false, false)?;
+
+    eprintln!("✂️  Replacing links to attachments in issues, pull requests, comments, reviews and releases...");
+    let messages = prune::rewrite_metadata_files(
+        &archive,
+        &collected.attachments_by_size,
+        &args.placeholder,
+        &args.output_directory,
+    )?;
+
+    Ok(Results {
+        messages,
+        missing_attachments,
+        breached_limits: Vec::new(),
+    })
+}
+
+fn slim_archive(args: &SlimArgs) -> Result<Results, std::io::Error> {
+    let Collection {
+        archive,
+        collected,
+        missing_attachments,
+        ..
+    } = collect_subcommand_attachments(&args.common, Some(args.min_size), false, false)?;
+
+    eprintln!(
+        "📦 Writing slimmed-down archive to {}...",
+        args.output.display()
+    );
+    let messages = slim::write_slimmed_archive(
+        &archive,
+        &collected.attachments_by_size,
+        &args.output,
+        args.common.skip_invalid_metadata,
+    )?;
+
+    Ok(Results {
+        messages,
+        missing_attachments,
+        breached_limits: Vec::new(),
+    })
+}
+
+fn remediate_attachments(args: &RemediateArgs) -> Result<Results, std::io::Error> {
+    let Collection {
+        archive,
+        collected,
+        missing_attachments,
+        ..
+    } = collect_subcommand_attachments(&args.common, Some(args.min_size), false, false)?;
+
+    eprintln!("🔗 Finding links to attachments in issues, pull requests, comments, reviews and releases...");
+    let links = prune::find_links(&archive, &collected.attachments_by_size)?;
+
+    if links.is_empty() {
+        eprintln!("🎉 No links to attachments need removing");
+    }
+
+    let messages = match args.format {
+        RemediationFormat::Script => remediate::format_as_script(&links),
+        RemediationFormat::Json => remediate::format_as_json(&links)?,
+    };
 
-    Ok(messages)
+    Ok(Results {
+        messages,
+        missing_attachments,
+        breached_limits: Vec::new(),
+    })
+}
+
+fn export_attachments(args: &ExportArgs) -> Result<Results, std::io::Error> {
+    let Collection {
+        archive,
+        collected,
+        mut missing_attachments,
+        ..
+    } = collect_subcommand_attachments(&args.common, None, true, true)?;
+    let mut attachments_by_size = collected.attachments_by_size;
+
+    parents::resolve_parents(
+        &archive,
+        attachments_by_size
+            .iter_mut()
+            .map(|sized_attachment| (&sized_attachment.attachment, &mut sized_attachment.parent))
+            .chain(missing_attachments.iter_mut().map(|missing_attachment| {
+                (
+                    &missing_attachment.attachment,
+                    &mut missing_attachment.parent,
+                )
+            })),
+    );
+
+    eprintln!(
+        "🗃️  Writing SQLite database to {}...",
+        args.output.display()
+    );
+    let messages =
+        sqlite::write_database(&attachments_by_size, &missing_attachments, &args.output)?;
+
+    Ok(Results {
+        messages,
+        missing_attachments,
+        breached_limits: Vec::new(),
+    })
+}
+
+fn process_attachments(args: &Args) -> Result<Results, std::io::Error> {
+    match &args.command {
+        Some(Command::Prune(prune_args)) => return prune_attachments(prune_args),
+        Some(Command::Slim(slim_args)) => return slim_archive(slim_args),
+        Some(Command::Remediate(remediate_args)) => return remediate_attachments(remediate_args),
+        Some(Command::Export(export_args)) => return export_attachments(export_args),
+        None => {}
+    }
+
+    if args.format.only_lists_attachments()
+        && (args.group_by.is_some() || args.find_duplicates || args.find_orphans)
+    {
+        let error_message = format!(
+            "`--format {}` can only be used to list attachments, not with --group-by, --find-duplicates or --find-orphans",
+            clap::ValueEnum::to_possible_value(&args.format).unwrap().get_name()
+        );
+        return Err(std::io::Error::other(error_message));
+    }
+
+    let working_directory = get_working_directory(args.archive.clone());
+
+    let open_options = OpenOptions {
+        hash_assets: args.find_duplicates,
+        skip_invalid_metadata: args.skip_invalid_metadata,
+        // Parents are only shown when we're listing attachments
+        collect_parent_records: args.group_by.is_none()
+            && !args.find_duplicates
+            && !args.find_orphans,
+    };
+    let Collection {
+        archive,
+        collected,
+        missing_attachments,
+        referenced_paths,
+    } = collect_attachments(
+        &working_directory,
+        &open_options,
+        Collector::new(args.min_size, args.top, args.group_by.clone()),
+        get_jobs(args.jobs),
+        args.find_orphans,
+    )?;
+    let breached_limits = check_size_limits(args, &collected);
+    let mut attachments_by_size = collected.attachments_by_size;
+
+    let mut messages = if args.find_orphans {
+        eprintln!("🧭 Looking for files which aren't used by any attachment...");
+        let orphaned_files = summary::find_orphans(archive.asset_files()?, &referenced_paths);
+
+        if orphaned_files.is_empty() {
+            eprintln!("🎉 No orphaned files found");
+        }
+
+        match args.format {
+            OutputFormat::Text => output::format_orphans_as_text(&orphaned_files),
+            OutputFormat::Json => output::format_orphans_as_json(&orphaned_files)?,
+            OutputFormat::Csv => output::format_orphans_as_csv(&orphaned_files)?,
+            OutputFormat::Html | OutputFormat::Markdown => {
+                unreachable!("HTML and Markdown reports only list attachments")
+            }
+        }
+    } else if args.find_duplicates {
+        eprintln!("👯 Finding attachments with identical contents...");
+        let duplicate_sets = summary::find_duplicates(&attachments_by_size);
+
+        if duplicate_sets.is_empty() {
+            eprintln!("🎉 No duplicate attachments found");
+        }
+
+        match args.format {
+            OutputFormat::Text => output::format_duplicates_as_text(&duplicate_sets),
+            OutputFormat::Json => output::format_duplicates_as_json(&duplicate_sets)?,
+            OutputFormat::Csv => output::format_duplicates_as_csv(&duplicate_sets)?,
+            OutputFormat::Html | OutputFormat::Markdown => {
+                unreachable!("HTML and Markdown reports only list attachments")
+            }
+        }
+    } else if let (Some(group_by), Some(groups)) = (&args.group_by, &collected.groups) {
+        match args.format {
+            OutputFormat::Text => output::format_groups_as_text(groups),
+            OutputFormat::Json => output::format_groups_as_json(groups, group_by)?,
+            OutputFormat::Csv => output::format_groups_as_csv(groups, group_by)?,
+            OutputFormat::Html | OutputFormat::Markdown => {
+                unreachable!("HTML and Markdown reports only list attachments")
+            }
+        }
+    } else {
+        parents::resolve_parents(
+            &archive,
+            attachments_by_size.iter_mut().map(|sized_attachment| {
+                (&sized_attachment.attachment, &mut sized_attachment.parent)
+            }),
+        );
+
+        match args.format {
+            OutputFormat::Text => output::format_as_text(&attachments_by_size),
+            OutputFormat::Json => output::format_as_json(&attachments_by_size)?,
+            OutputFormat::Csv => output::format_as_csv(&attachments_by_size)?,
+            OutputFormat::Html => {
+                eprintln!("🖼️  Reading images for thumbnails...");
+                let thumbnails =
+                    archive.read_assets(&html::thumbnail_asset_urls(&attachments_by_size))?;
+                html::format_as_html(&attachments_by_size, &thumbnails, &archive.describe())
+            }
+            OutputFormat::Markdown => output::format_as_markdown(&attachments_by_size),
+        }
+    };
+
+    if collected.hidden_count > 0 {
+        let trailer = format!(
+            "🙈 {} more attachment(s) totalling {} hidden by --min-size and/or --top",
+            collected.hidden_count,
+            output::format_size(collected.hidden_size)
+        );
+
+        // Keep the JSON and CSV output parseable by sending the trailer to stderr instead
+        match args.format {
+            OutputFormat::Text => messages.push(trailer),
+            _ => eprintln!("{}", trailer),
+        }
+    }
+
+    Ok(Results {
+        messages,
+        missing_attachments,
+        breached_limits,
+    })
+}
+
+// Returns a message for each of the --fail-if-total-over and --fail-if-any-over limits that the
+// attachments went over
+fn check_size_limits(args: &Args, collected: &Collected) -> Vec<String> {
+    let mut breached_limits: Vec<String> = Vec::new();
+
+    if let Some(limit) = args.fail_if_total_over {
+        if collected.total_size > limit {
+            breached_limits.push(format!(
+                "The attachments total {}, over the --fail-if-total-over limit of {}",
+                output::format_size(collected.total_size),
+                output::format_size(limit)
+            ));
+        }
+    }
+
+    if let (Some(limit), Some((asset_name, size))) =
+        (args.fail_if_any_over, &collected.largest_attachment)
+    {
+        if *size > limit {
+            breached_limits.push(format!(
+                "The largest attachment, {}, is {}, over the --fail-if-any-over limit of {}",
+                asset_name,
+                output::format_size(*size),
+                output::format_size(limit)
+            ));
+        }
+    }
+
+    breached_limits
 }
 
 fn main() -> Result<(), std::io::Error> {
-    let result = process_attachments(None);
+    let args = Args::parse();
+
+    let result = process_attachments(&args);
 
     match result {
-        Ok(messages) => {
-            for message in messages.iter() {
+        Ok(results) => {
+            for message in results.messages.iter() {
                 println!("{}", message)
             }
 
+            for breached_limit in results.breached_limits.iter() {
+                eprintln!("🚨 {}", breached_limit);
+            }
+
+            // Missing files take priority, since the sizes we checked the limits against don't
+            // include them
+            if args.strict && !results.missing_attachments.is_empty() {
+                eprintln!(
+                    "Error: {} attachment file(s) were missing from the archive, and --strict was set",
+                    results.missing_attachments.len()
+                );
+                std::process::exit(exitcode::NOINPUT);
+            }
+
+            if !results.breached_limits.is_empty() {
+                std::process::exit(LIMIT_BREACHED_EXIT_CODE);
+            }
+
             std::process::exit(exitcode::OK);
         }
         Err(e) => {
@@ -162,13 +867,43 @@ fn main() -> Result<(), std::io::Error> {
 
 #[cfg(test)]
 mod tests {
+    use base64::Engine;
+    use clap::Parser;
+    use std::path::{Path, PathBuf};
+
+    // A directory for a test to write files to, which is deleted when the test finishes, even if
+    // it fails
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(test_name: &str) -> TempDir {
+            let path =
+                std::env::temp_dir().join(format!("gaaa-{}-{}", test_name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+
+            TempDir(path)
+        }
+
+        fn path(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
     #[test]
     fn it_identifies_attachments_in_single_file() {
-        let result = super::process_attachments(Some("fixtures/single-file".to_string()));
+        let result =
+            super::process_attachments(&super::Args::parse_from(["gaaa", "fixtures/single-file"]));
 
         match result {
             Ok(val) => {
-                assert_eq!(val, vec!["todd-trapani-QldMpmrmWuc-unsplash.jpg (https://github.com/caffeinesoftware/rewardnights/pull/337) - 141 KiB"])
+                assert_eq!(val.messages, vec!["todd-trapani-QldMpmrmWuc-unsplash.jpg (https://github.com/caffeinesoftware/rewardnights/pull/337) - 141 KiB"])
             }
             Err(e) => {
                 panic!("process_attachments returned an error: {}", e)
@@ -178,11 +913,51 @@ mod tests {
 
     #[test]
     fn it_identifies_attachments_across_multiple_files() {
-        let result = super::process_attachments(Some("fixtures/multiple-files".to_string()));
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-files",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![
+                    "todd-trapani-QldMpmrmWuc-unsplash-2.jpg (https://github.com/caffeinesoftware/rewardnights/pull/337) - 141 KiB",
+                    "todd-trapani-QldMpmrmWuc-unsplash.jpg (https://github.com/caffeinesoftware/rewardnights/pull/337) - 141 KiB"
+                ])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_identifies_attachments_in_a_gzipped_tarball() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/single-file.tar.gz",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec!["todd-trapani-QldMpmrmWuc-unsplash.jpg (https://github.com/caffeinesoftware/rewardnights/pull/337) - 141 KiB"])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_identifies_attachments_across_multiple_files_in_a_tarball() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-files.tar",
+        ]));
 
         match result {
             Ok(val) => {
-                assert_eq!(val, vec![
+                assert_eq!(val.messages, vec![
                     "todd-trapani-QldMpmrmWuc-unsplash-2.jpg (https://github.com/caffeinesoftware/rewardnights/pull/337) - 141 KiB",
                     "todd-trapani-QldMpmrmWuc-unsplash.jpg (https://github.com/caffeinesoftware/rewardnights/pull/337) - 141 KiB"
                 ])
@@ -193,9 +968,1470 @@ mod tests {
         }
     }
 
+    #[test]
+    fn it_identifies_release_assets_alongside_attachments() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/with-releases",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![
+                    "rewardnights-linux-amd64.zip (https://github.com/caffeinesoftware/rewardnights/releases/tag/v1.0.0) - 256 KiB",
+                    "todd-trapani-QldMpmrmWuc-unsplash.jpg (https://github.com/caffeinesoftware/rewardnights/pull/337) - 141 KiB"
+                ])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_shows_issue_and_pull_request_titles_from_a_tarball() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-repositories.tar.gz",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![
+                    "demo.mov (https://github.com/caffeinesoftware/rewardnights/pull/340) - 10 KiB - \"Add a demo of the new booking flow\" (merged, updated 2023-02-05)",
+                    "recording.mov (https://github.com/caffeinesoftware/website/issues/5) - 6 KiB - \"Hero video stutters on load\" (closed, updated 2023-02-10)",
+                    "logs.zip (https://github.com/caffeinesoftware/website/issues/5#issuecomment-1001) - 4 KiB - comment by octocat on #5 \"Hero video stutters on load\" (closed, updated 2023-02-10)",
+                    "screenshot.png (https://github.com/caffeinesoftware/rewardnights/issues/12) - 3 KiB - \"Checkout page renders blank on Safari\" (open, updated 2023-02-06)"
+                ])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_reports_tarballs_that_do_not_exist() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/does-not-exist.tar.gz",
+        ]));
+
+        match result {
+            Ok(_) => panic!("process_attachments should have returned an error"),
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Could not find archive file `fixtures/does-not-exist.tar.gz`"
+            ),
+        }
+    }
+
+    #[test]
+    fn it_reports_which_tarball_could_not_be_read() {
+        let temp_dir = TempDir::new("it-reports-which-tarball-could-not-be-read");
+        let output_directory = temp_dir.path();
+
+        // Cut the tarball off part of the way through, like an interrupted download
+        let tarball = std::fs::read("fixtures/multiple-repositories.tar.gz").unwrap();
+        let truncated_path = output_directory.join("truncated.tar.gz");
+        std::fs::write(&truncated_path, &tarball[..tarball.len() * 7 / 10]).unwrap();
+
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            truncated_path.to_str().unwrap(),
+        ]));
+
+        match result {
+            Ok(_) => panic!("process_attachments should have returned an error"),
+            Err(e) => assert!(e
+                .to_string()
+                .starts_with(&format!("Could not read `{}`: ", truncated_path.display()))),
+        }
+    }
+
+    #[test]
+    fn it_identifies_release_assets_in_a_tarball() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/with-releases.tar.gz",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![
+                    "rewardnights-linux-amd64.zip (https://github.com/caffeinesoftware/rewardnights/releases/tag/v1.0.0) - 256 KiB",
+                    "todd-trapani-QldMpmrmWuc-unsplash.jpg (https://github.com/caffeinesoftware/rewardnights/pull/337) - 141 KiB"
+                ])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_hides_attachments_smaller_than_the_minimum_size() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/with-releases",
+            "--min-size",
+            "200KiB",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![
+                    "rewardnights-linux-amd64.zip (https://github.com/caffeinesoftware/rewardnights/releases/tag/v1.0.0) - 256 KiB",
+                    "🙈 1 more attachment(s) totalling 141 KiB hidden by --min-size and/or --top"
+                ])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_only_shows_the_top_attachments() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-files",
+            "--top",
+            "1",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![
+                    "todd-trapani-QldMpmrmWuc-unsplash-2.jpg (https://github.com/caffeinesoftware/rewardnights/pull/337) - 141 KiB",
+                    "🙈 1 more attachment(s) totalling 141 KiB hidden by --min-size and/or --top"
+                ])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_groups_only_the_top_attachments() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-repositories",
+            "--group-by",
+            "repository",
+            "--top",
+            "2",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(
+                    val.messages,
+                    vec![
+                        "caffeinesoftware/rewardnights - 1 attachment(s) - 10 KiB",
+                        "caffeinesoftware/website - 1 attachment(s) - 6 KiB",
+                        "🙈 2 more attachment(s) totalling 7 KiB hidden by --min-size and/or --top"
+                    ]
+                )
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_returns_the_same_results_whether_run_sequentially_or_in_parallel() {
+        let sequential_result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-repositories",
+            "--jobs",
+            "1",
+        ]))
+        .unwrap();
+        let parallel_result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-repositories",
+            "--jobs",
+            "3",
+        ]))
+        .unwrap();
+
+        assert_eq!(sequential_result.messages.len(), 4);
+        assert_eq!(sequential_result.messages, parallel_result.messages);
+    }
+
+    #[test]
+    fn it_parses_human_readable_minimum_sizes() {
+        assert_eq!(super::parse_size("10MiB"), Ok(10 * 1024 * 1024));
+        assert_eq!(super::parse_size("500kb"), Ok(500 * 1000));
+        assert!(super::parse_size("lots").is_err());
+    }
+
+    #[test]
+    fn it_groups_attachments_by_repository() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-repositories",
+            "--group-by",
+            "repository",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(
+                    val.messages,
+                    vec![
+                        "caffeinesoftware/rewardnights - 2 attachment(s) - 13 KiB",
+                        "caffeinesoftware/website - 2 attachment(s) - 10 KiB"
+                    ]
+                )
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_groups_attachments_by_user() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-repositories",
+            "--group-by",
+            "user",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(
+                    val.messages,
+                    vec![
+                        "timrogers - 2 attachment(s) - 16 KiB",
+                        "octocat - 1 attachment(s) - 4 KiB",
+                        "dependabot[bot] - 1 attachment(s) - 3 KiB"
+                    ]
+                )
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_flags_bot_users_in_csv_output() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-repositories",
+            "--group-by",
+            "user",
+            "--format",
+            "csv",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(
+                    val.messages,
+                    vec![[
+                        "user,count,size,bot",
+                        "timrogers,2,16384,false",
+                        "octocat,1,4096,false",
+                        "dependabot[bot],1,3072,true"
+                    ]
+                    .join("\n")]
+                )
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_groups_attachments_by_content_type() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-repositories",
+            "--group-by",
+            "content-type",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(
+                    val.messages,
+                    vec![
+                        "video/quicktime - 1 attachment(s) - 10 KiB",
+                        "application/octet-stream (.mov) - 1 attachment(s) - 6 KiB",
+                        "application/zip - 1 attachment(s) - 4 KiB",
+                        "image/png - 1 attachment(s) - 3 KiB"
+                    ]
+                )
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_finds_duplicate_attachments() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-files",
+            "--find-duplicates",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![
+                    "2 copies of todd-trapani-QldMpmrmWuc-unsplash-2.jpg - 141 KiB each, 141 KiB wasted",
+                    "  todd-trapani-QldMpmrmWuc-unsplash-2.jpg (https://github.com/caffeinesoftware/rewardnights/pull/337)",
+                    "  todd-trapani-QldMpmrmWuc-unsplash.jpg (https://github.com/caffeinesoftware/rewardnights/pull/337)",
+                    "♻️ Removing duplicates would save 141 KiB"
+                ])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_finds_duplicate_attachments_in_a_tarball() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-files.tar",
+            "--find-duplicates",
+            "--format",
+            "csv",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![[
+                    "sha256,asset_name,parent_url,size,path",
+                    "c514876d64a63b0315fef943b7f34a36215793da6b84e85240511d3be14189be,todd-trapani-QldMpmrmWuc-unsplash-2.jpg,https://github.com/caffeinesoftware/rewardnights/pull/337,144106,attachments/774d3d0d-f4a9-4b93-b27b-5a3b7f44ff32/todd-trapani-QldMpmrmWuc-unsplash-2.jpg",
+                    "c514876d64a63b0315fef943b7f34a36215793da6b84e85240511d3be14189be,todd-trapani-QldMpmrmWuc-unsplash.jpg,https://github.com/caffeinesoftware/rewardnights/pull/337,144106,attachments/774d3d0d-f4a9-4b93-b27b-5a3b7f44ff31/todd-trapani-QldMpmrmWuc-unsplash.jpg"
+                ].join("\n")])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_finds_no_duplicates_when_all_attachments_are_unique() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-repositories",
+            "--find-duplicates",
+        ]));
+
+        match result {
+            Ok(val) => assert!(val.messages.is_empty()),
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_finds_orphaned_files() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/orphaned-files",
+            "--find-orphans",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![
+                    "fixtures/orphaned-files/attachments/e2b7c9a1-4d6f-4a3b-8c5d-0f1e2d3c4b5a/old-screenshot.png - 2 KiB",
+                    "🧹 1 orphaned file(s) totalling 2 KiB aren't used by any issue, pull request or release"
+                ])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_finds_orphaned_files_in_a_tarball() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/orphaned-files.tar.gz",
+            "--find-orphans",
+            "--format",
+            "csv",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(
+                    val.messages,
+                    vec![[
+                        "path,size",
+                        "attachments/e2b7c9a1-4d6f-4a3b-8c5d-0f1e2d3c4b5a/old-screenshot.png,2048"
+                    ]
+                    .join("\n")]
+                )
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_outputs_groups_as_json() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-repositories",
+            "--group-by",
+            "repository",
+            "--format",
+            "json",
+        ]));
+
+        match result {
+            Ok(val) => {
+                let json: serde_json::Value =
+                    serde_json::from_str(&val.messages.join("\n")).unwrap();
+
+                assert_eq!(
+                    json,
+                    serde_json::json!([
+                        {
+                            "repository": "caffeinesoftware/rewardnights",
+                            "count": 2,
+                            "size": 13312,
+                            "human_size": "13 KiB"
+                        },
+                        {
+                            "repository": "caffeinesoftware/website",
+                            "count": 2,
+                            "size": 10240,
+                            "human_size": "10 KiB"
+                        }
+                    ])
+                )
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_outputs_attachments_as_json() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/single-file",
+            "--format",
+            "json",
+        ]));
+
+        match result {
+            Ok(val) => {
+                let json: serde_json::Value =
+                    serde_json::from_str(&val.messages.join("\n")).unwrap();
+
+                assert_eq!(
+                    json,
+                    serde_json::json!([{
+                        "type": "attachment",
+                        "url": "https://user-images.githubusercontent.com/845662/192706685-774d3d0d-f4a9-4b93-b27b-5a3b7f44ff31.jpg",
+                        "pull_request": "https://github.com/caffeinesoftware/rewardnights/pull/337",
+                        "issue": null,
+                        "issue_comment": null,
+                        "pull_request_review": null,
+                        "pull_request_review_comment": null,
+                        "commit_comment": null,
+                        "release": null,
+                        "user": "https://github.com/dependabot[bot]",
+                        "asset_name": "todd-trapani-QldMpmrmWuc-unsplash.jpg",
+                        "asset_content_type": "image/jpeg",
+                        "asset_url": "tarball://root/attachments/774d3d0d-f4a9-4b93-b27b-5a3b7f44ff31/todd-trapani-QldMpmrmWuc-unsplash.jpg",
+                        "created_at": "2023-01-11T08:16:07Z",
+                        "path": "fixtures/single-file/attachments/774d3d0d-f4a9-4b93-b27b-5a3b7f44ff31/todd-trapani-QldMpmrmWuc-unsplash.jpg",
+                        "size": 144106,
+                        "human_size": "141 KiB",
+                        "parent": null
+                    }])
+                )
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_outputs_every_parent_field_for_every_attachment_as_json() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/other-parents",
+            "--format",
+            "json",
+        ]));
+
+        match result {
+            Ok(val) => {
+                let json: serde_json::Value =
+                    serde_json::from_str(&val.messages.join("\n")).unwrap();
+
+                // Whether or not we found a parent, every attachment has the same fields
+                let key_sets: Vec<Vec<&String>> = json
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|attachment| attachment.as_object().unwrap().keys().collect())
+                    .collect();
+                assert!(key_sets.iter().all(|keys| *keys == key_sets[0]));
+
+                for attachment in json.as_array().unwrap() {
+                    for key in [
+                        "pull_request",
+                        "issue",
+                        "issue_comment",
+                        "pull_request_review",
+                        "pull_request_review_comment",
+                        "commit_comment",
+                        "release",
+                    ] {
+                        assert!(
+                            attachment.get(key).is_some(),
+                            "{} is missing `{}`",
+                            attachment["asset_name"],
+                            key
+                        );
+                    }
+                }
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_outputs_attachments_as_csv() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/with-releases",
+            "--format",
+            "csv",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![[
+                    "asset_name,asset_content_type,parent_url,user,created_at,size,path,parent_title,parent_state,parent_updated_at",
+                    "rewardnights-linux-amd64.zip,application/zip,https://github.com/caffeinesoftware/rewardnights/releases/tag/v1.0.0,https://github.com/timrogers,2023-01-12T09:25:00Z,262144,fixtures/with-releases/release_assets/5b1e2f4c-0c7a-4d3e-9a41-2f5d8c1e7b90/rewardnights-linux-amd64.zip,,,",
+                    "todd-trapani-QldMpmrmWuc-unsplash.jpg,image/jpeg,https://github.com/caffeinesoftware/rewardnights/pull/337,https://github.com/dependabot[bot],2023-01-11T08:16:07Z,144106,fixtures/with-releases/attachments/774d3d0d-f4a9-4b93-b27b-5a3b7f44ff31/todd-trapani-QldMpmrmWuc-unsplash.jpg,,,"
+                ].join("\n")])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_outputs_attachments_as_an_html_report() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-repositories",
+            "--format",
+            "html",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages.len(), 1);
+                let html = &val.messages[0];
+
+                assert!(html.starts_with("<!DOCTYPE html>"));
+                assert!(html.contains("<p>4 attachment(s) totalling 23 KiB</p>"));
+                assert!(html.contains(
+                    "<tr><td>caffeinesoftware/rewardnights</td><td data-sort=\"2\">2</td><td class=\"size\" data-sort=\"13312\">13 KiB</td></tr>"
+                ));
+                assert!(html.contains(
+                    "<tr><td>caffeinesoftware/website</td><td data-sort=\"2\">2</td><td class=\"size\" data-sort=\"10240\">10 KiB</td></tr>"
+                ));
+
+                // Only the PNG is an image, so it's the only attachment with a thumbnail
+                assert_eq!(html.matches("<img class=\"thumbnail\"").count(), 1);
+                let screenshot = std::fs::read("fixtures/multiple-repositories/attachments/3f1c2a9e-6b1d-4c8e-9f0a-1b2c3d4e5f60/screenshot.png").unwrap();
+                assert!(html.contains(&format!(
+                    "src=\"data:image/png;base64,{}\">",
+                    base64::engine::general_purpose::STANDARD.encode(screenshot)
+                )));
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_outputs_attachments_as_markdown_task_lists_per_repository() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-repositories",
+            "--format",
+            "markdown",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![[
+                    "## caffeinesoftware/rewardnights",
+                    "",
+                    "2 attachment(s) totalling 13 KiB",
+                    "",
+                    "- [ ] demo.mov — 10 KiB — https://github.com/caffeinesoftware/rewardnights/pull/340",
+                    "- [ ] screenshot.png — 3 KiB — https://github.com/caffeinesoftware/rewardnights/issues/12",
+                    "",
+                    "## caffeinesoftware/website",
+                    "",
+                    "2 attachment(s) totalling 10 KiB",
+                    "",
+                    "- [ ] recording.mov — 6 KiB — https://github.com/caffeinesoftware/website/issues/5",
+                    "- [ ] logs.zip — 4 KiB — https://github.com/caffeinesoftware/website/issues/5#issuecomment-1001"
+                ].join("\n")])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_rejects_html_reports_for_summaries() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-repositories",
+            "--group-by",
+            "repository",
+            "--format",
+            "html",
+        ]));
+
+        match result {
+            Ok(_) => panic!("process_attachments should have returned an error"),
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "`--format html` can only be used to list attachments, not with --group-by, --find-duplicates or --find-orphans"
+            ),
+        }
+    }
+
+    #[test]
+    fn it_reports_size_limits_that_were_breached() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-repositories",
+            "--top",
+            "1",
+            "--fail-if-total-over",
+            "20KiB",
+            "--fail-if-any-over",
+            "8KiB",
+        ]));
+
+        match result {
+            Ok(val) => {
+                // Attachments hidden by --top still count towards the total
+                assert_eq!(
+                    val.breached_limits,
+                    vec![
+                        "The attachments total 23 KiB, over the --fail-if-total-over limit of 20 KiB",
+                        "The largest attachment, demo.mov, is 10 KiB, over the --fail-if-any-over limit of 8 KiB"
+                    ]
+                )
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_does_not_report_size_limits_that_were_not_breached() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-repositories",
+            "--fail-if-total-over",
+            "23KiB",
+            "--fail-if-any-over",
+            "10KiB",
+        ]));
+
+        match result {
+            Ok(val) => assert!(val.breached_limits.is_empty()),
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_outputs_a_csv_header_when_there_are_no_attachments() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/single-file",
+            "--format",
+            "csv",
+            "--min-size",
+            "1GiB",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![
+                    "asset_name,asset_content_type,parent_url,user,created_at,size,path,parent_title,parent_state,parent_updated_at"
+                ])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_outputs_a_csv_header_when_there_are_no_duplicates() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-repositories",
+            "--find-duplicates",
+            "--format",
+            "csv",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec!["sha256,asset_name,parent_url,size,path"])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_parses_the_archive_and_format_arguments() {
+        let args = super::Args::parse_from(["gaaa", "--format", "json", "archive.tar.gz"]);
+
+        assert_eq!(args.archive, Some("archive.tar.gz".to_string()));
+        assert_eq!(args.format, super::OutputFormat::Json);
+    }
+
+    #[test]
+    fn it_rejects_unknown_output_formats() {
+        let result = super::Args::try_parse_from(["gaaa", "--format", "yaml"]);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn it_skips_attachments_whose_files_are_missing() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/missing-files",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![
+                    "todd-trapani-QldMpmrmWuc-unsplash.jpg (https://github.com/caffeinesoftware/rewardnights/pull/337) - 141 KiB"
+                ]);
+                assert_eq!(val.missing_attachments.len(), 1);
+                assert_eq!(
+                    val.missing_attachments[0].attachment.issue,
+                    Some("https://github.com/caffeinesoftware/rewardnights/issues/12".to_string())
+                );
+                assert_eq!(
+                    val.missing_attachments[0].path,
+                    "fixtures/missing-files/attachments/9d0c7e8b-2f3a-4b5c-8d6e-7f8091a2b3c4/deleted-recording.mp4"
+                );
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_sizes_attachments_whose_files_come_before_their_metadata_in_a_tarball() {
+        let temp_dir = TempDir::new(
+            "it-sizes-attachments-whose-files-come-before-their-metadata-in-a-tarball",
+        );
+        let output_directory = temp_dir.path();
+
+        // The attachment's file comes first, so it can be sized as soon as its metadata is read,
+        // while the missing attachment is only passed on once we reach the end of the tarball
+        let tarball_path = output_directory.join("files-first.tar");
+        let mut builder = tar::Builder::new(std::fs::File::create(&tarball_path).unwrap());
+        builder
+            .append_path_with_name(
+                "fixtures/missing-files/attachments/774d3d0d-f4a9-4b93-b27b-5a3b7f44ff31/todd-trapani-QldMpmrmWuc-unsplash.jpg",
+                "attachments/774d3d0d-f4a9-4b93-b27b-5a3b7f44ff31/todd-trapani-QldMpmrmWuc-unsplash.jpg",
+            )
+            .unwrap();
+        builder
+            .append_path_with_name(
+                "fixtures/missing-files/attachments_000001.json",
+                "attachments_000001.json",
+            )
+            .unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            tarball_path.to_str().unwrap(),
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![
+                    "todd-trapani-QldMpmrmWuc-unsplash.jpg (https://github.com/caffeinesoftware/rewardnights/pull/337) - 141 KiB"
+                ]);
+                assert_eq!(val.missing_attachments.len(), 1);
+                assert_eq!(
+                    val.missing_attachments[0].path,
+                    "attachments/9d0c7e8b-2f3a-4b5c-8d6e-7f8091a2b3c4/deleted-recording.mp4"
+                );
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_errors_with_context_if_a_metadata_file_is_invalid() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/invalid-metadata",
+        ]));
+
+        match result {
+            Ok(_val) => {
+                panic!("process_attachments returned a value, but was expected to error");
+            }
+            Err(e) => {
+                assert_eq!(e.to_string(), "Could not read attachments metadata files: Could not parse metadata file `fixtures/invalid-metadata/attachments_000002.json` at line 20, column 3 (entry at index 1): missing field `asset_url`".to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn it_skips_invalid_metadata_files_if_asked() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/invalid-metadata",
+            "--skip-invalid-metadata",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![
+                    "todd-trapani-QldMpmrmWuc-unsplash.jpg (https://github.com/caffeinesoftware/rewardnights/pull/337) - 141 KiB"
+                ])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_shows_the_details_of_each_attachments_issue_or_pull_request() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-repositories",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![
+                    "demo.mov (https://github.com/caffeinesoftware/rewardnights/pull/340) - 10 KiB - \"Add a demo of the new booking flow\" (merged, updated 2023-02-05)",
+                    "recording.mov (https://github.com/caffeinesoftware/website/issues/5) - 6 KiB - \"Hero video stutters on load\" (closed, updated 2023-02-10)",
+                    "logs.zip (https://github.com/caffeinesoftware/website/issues/5#issuecomment-1001) - 4 KiB - comment by octocat on #5 \"Hero video stutters on load\" (closed, updated 2023-02-10)",
+                    "screenshot.png (https://github.com/caffeinesoftware/rewardnights/issues/12) - 3 KiB - \"Checkout page renders blank on Safari\" (open, updated 2023-02-06)",
+                ])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_includes_the_details_of_each_attachments_issue_or_pull_request_in_json() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/multiple-repositories",
+            "--format",
+            "json",
+            "--top",
+            "3",
+        ]));
+
+        match result {
+            Ok(val) => {
+                let attachments: serde_json::Value =
+                    serde_json::from_str(&val.messages[0]).unwrap();
+
+                assert_eq!(
+                    attachments[0]["parent"],
+                    serde_json::json!({
+                        "url": "https://github.com/caffeinesoftware/rewardnights/pull/340",
+                        "number": 340,
+                        "title": "Add a demo of the new booking flow",
+                        "state": "merged",
+                        "updated_at": "2023-02-05T14:00:00Z",
+                        "comment_author": null
+                    })
+                );
+                assert_eq!(
+                    attachments[2]["parent"],
+                    serde_json::json!({
+                        "url": "https://github.com/caffeinesoftware/website/issues/5",
+                        "number": 5,
+                        "title": "Hero video stutters on load",
+                        "state": "closed",
+                        "updated_at": "2023-02-10T16:30:00Z",
+                        "comment_author": "octocat"
+                    })
+                );
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_lists_attachments_on_reviews_commit_comments_releases_and_unknown_parents() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "fixtures/other-parents",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![
+                    "diff.png (https://github.com/caffeinesoftware/rewardnights/pull/340#discussion_r2001) - 5 KiB - review comment by octocat on #340 \"Add a demo of the new booking flow\" (merged, updated 2023-02-05)",
+                    "review.png (https://github.com/caffeinesoftware/rewardnights/pull/340#pullrequestreview-3001) - 4 KiB - review by hubot on #340 \"Add a demo of the new booking flow\" (merged, updated 2023-02-05)",
+                    "crash.log (https://github.com/caffeinesoftware/rewardnights/commit/0d1d7fc35a4b9e8f6c3b2a1908f7e6d5c4b3a291#commitcomment-4001) - 3 KiB",
+                    "banner.png (https://github.com/caffeinesoftware/rewardnights/releases/tag/v1.1.0) - 2 KiB",
+                    "mystery.pdf (unknown parent) - 1 KiB",
+                ])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_writes_remediation_steps_for_reviews_and_commit_comments() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "remediate",
+            "fixtures/other-parents",
+            "--min-size",
+            "3KiB",
+            "--format",
+            "json",
+        ]));
+
+        match result {
+            Ok(val) => {
+                let plan: serde_json::Value = serde_json::from_str(&val.messages[0]).unwrap();
+                let steps: Vec<(&str, &str)> = plan
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|step| {
+                        (
+                            step["method"].as_str().unwrap(),
+                            step["endpoint"].as_str().unwrap(),
+                        )
+                    })
+                    .collect();
+
+                assert_eq!(
+                    steps,
+                    vec![
+                        (
+                            "PUT",
+                            "repos/caffeinesoftware/rewardnights/pulls/340/reviews/3001"
+                        ),
+                        (
+                            "PATCH",
+                            "repos/caffeinesoftware/rewardnights/pulls/comments/2001"
+                        ),
+                        ("PATCH", "repos/caffeinesoftware/rewardnights/comments/4001"),
+                    ]
+                );
+
+                // Images which link to the attachment and HTML links are removed whole, so they
+                // don't leave any broken markup behind
+                let texts: Vec<&str> = plan
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|step| step["text_to_remove"].as_str().unwrap())
+                    .collect();
+
+                assert_eq!(texts, vec![
+                    "[![review](https://user-images.githubusercontent.com/845662/300000002-a1b2c3d4-0002-4000-8000-000000000002-thumbnail.png)](https://user-images.githubusercontent.com/845662/300000002-a1b2c3d4-0002-4000-8000-000000000002.png)",
+                    "![diff](https://user-images.githubusercontent.com/845662/300000001-a1b2c3d4-0001-4000-8000-000000000001.png)",
+                    "<a href=\"https://user-images.githubusercontent.com/845662/300000003-a1b2c3d4-0003-4000-8000-000000000003.log\">crash.log</a>",
+                ])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_prunes_links_to_large_attachments() {
+        let temp_dir = TempDir::new("it-prunes-links-to-large-attachments");
+        let output_directory = temp_dir.path();
+
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "prune",
+            "fixtures/multiple-repositories",
+            "--min-size",
+            "5KiB",
+            "--placeholder",
+            "https://example.com/removed",
+            "--output-directory",
+            output_directory.to_str().unwrap(),
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![
+                    "Removed recording.mov (6 KiB) from https://github.com/caffeinesoftware/website/issues/5",
+                    "Removed demo.mov (10 KiB) from https://github.com/caffeinesoftware/rewardnights/pull/340",
+                    &format!("✂️ Replaced 2 link(s) to 2 attachment(s) totalling 16 KiB, and wrote 2 modified metadata file(s) to `{}`", output_directory.display()),
+                ])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+
+        let pull_requests =
+            std::fs::read_to_string(output_directory.join("pull_requests_000001.json")).unwrap();
+        assert!(pull_requests.contains(
+            r#""body": "This adds the new booking flow. Here's a demo:\r\n\r\nhttps://example.com/removed","#
+        ));
+
+        // Small attachments are left alone, and files with nothing to replace aren't written
+        let issues = std::fs::read_to_string(output_directory.join("issues_000001.json")).unwrap();
+        assert!(issues.contains("200000000-3f1c2a9e-6b1d-4c8e-9f0a-1b2c3d4e5f60.png"));
+        assert!(!output_directory.join("issue_comments_000001.json").exists());
+    }
+
+    #[test]
+    fn it_counts_each_link_it_replaces_when_pruning() {
+        let temp_dir = TempDir::new("it-counts-each-link-it-replaces-when-pruning");
+        let output_directory = temp_dir.path();
+
+        let demo_url = "https://user-images.githubusercontent.com/845662/200000001-3f1c2a9e-6b1d-4c8e-9f0a-1b2c3d4e5f61.mov";
+        let recording_url = "https://user-images.githubusercontent.com/845662/200000003-3f1c2a9e-6b1d-4c8e-9f0a-1b2c3d4e5f63.mov";
+
+        // The pull request links to the demo twice, and the issue's link to the recording is
+        // written with escaped slashes, so it can't be replaced in the file
+        let pull_requests =
+            std::fs::read_to_string("fixtures/multiple-repositories/pull_requests_000001.json")
+                .unwrap()
+                .replace(
+                    "\"This adds the new booking flow.",
+                    &format!("\"{}\\r\\n\\r\\nThis adds the new booking flow.", demo_url),
+                );
+        let issues = std::fs::read_to_string("fixtures/multiple-repositories/issues_000001.json")
+            .unwrap()
+            .replace(recording_url, &recording_url.replace('/', "\\/"));
+
+        let tarball_path = output_directory.join("archive.tar");
+        let mut builder = tar::Builder::new(std::fs::File::create(&tarball_path).unwrap());
+        builder
+            .append_path_with_name(
+                "fixtures/multiple-repositories/attachments_000001.json",
+                "attachments_000001.json",
+            )
+            .unwrap();
+        for (file_name, contents) in [
+            ("pull_requests_000001.json", &pull_requests),
+            ("issues_000001.json", &issues),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, file_name, contents.as_bytes())
+                .unwrap();
+        }
+        builder
+            .append_dir_all("attachments", "fixtures/multiple-repositories/attachments")
+            .unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+
+        let pruned_directory = output_directory.join("pruned");
+
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "prune",
+            tarball_path.to_str().unwrap(),
+            "--min-size",
+            "5KiB",
+            "--output-directory",
+            pruned_directory.to_str().unwrap(),
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![
+                    "Removed demo.mov (10 KiB) from https://github.com/caffeinesoftware/rewardnights/pull/340",
+                    &format!("✂️ Replaced 2 link(s) to 1 attachment(s) totalling 10 KiB, and wrote 1 modified metadata file(s) to `{}`", pruned_directory.display()),
+                ])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+
+        assert!(!pruned_directory.join("issues_000001.json").exists());
+    }
+
+    #[test]
+    fn it_writes_a_slimmed_archive_without_large_attachments() {
+        let temp_dir = TempDir::new("it-writes-a-slimmed-archive-without-large-attachments");
+        let output_directory = temp_dir.path();
+        let output_path = output_directory.join("slimmed.tar.gz");
+
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "slim",
+            "fixtures/multiple-repositories",
+            "--min-size",
+            "5KiB",
+            "--output",
+            output_path.to_str().unwrap(),
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![
+                    "Removed demo.mov (https://github.com/caffeinesoftware/rewardnights/pull/340) - 10 KiB",
+                    "Removed recording.mov (https://github.com/caffeinesoftware/website/issues/5) - 6 KiB",
+                    &format!("📦 Wrote `{}`, leaving out 2 attachment(s) totalling 16 KiB", output_path.display()),
+                ])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+
+        // The slimmed archive is still a valid archive, with just the small attachments left
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            output_path.to_str().unwrap(),
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![
+                    "logs.zip (https://github.com/caffeinesoftware/website/issues/5#issuecomment-1001) - 4 KiB - comment by octocat on #5 \"Hero video stutters on load\" (closed, updated 2023-02-10)",
+                    "screenshot.png (https://github.com/caffeinesoftware/rewardnights/issues/12) - 3 KiB - \"Checkout page renders blank on Safari\" (open, updated 2023-02-06)",
+                ]);
+                assert!(val.missing_attachments.is_empty());
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_exports_attachments_to_a_sqlite_database() {
+        let temp_dir = TempDir::new("it-exports-attachments-to-a-sqlite-database");
+        let output_directory = temp_dir.path();
+        let output_path = output_directory.join("attachments.sqlite");
+
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "export",
+            "fixtures/multiple-repositories",
+            "--output",
+            output_path.to_str().unwrap(),
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(
+                    val.messages,
+                    vec![format!(
+                        "🗃️  Wrote 4 attachment(s) totalling 23 KiB and 3 parent(s) to `{}`",
+                        output_path.display()
+                    )]
+                )
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+
+        let connection = rusqlite::Connection::open(&output_path).unwrap();
+        let mut statement = connection
+            .prepare(
+                "SELECT attachments.asset_name, attachments.size, length(attachments.sha256), attachments.repository, attachments.comment_author, parents.number, parents.state
+                FROM attachments JOIN parents ON parents.id = attachments.parent_id
+                WHERE parents.state != 'open'
+                ORDER BY attachments.size DESC",
+            )
+            .unwrap();
+        let rows: Vec<String> = statement
+            .query_map([], |row| {
+                Ok(format!(
+                    "{} {} {} {} {:?} #{} {}",
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, i64>(5)?,
+                    row.get::<_, String>(6)?
+                ))
+            })
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+
+        // Only the attachments in closed or merged issues and pull requests, with their hashes
+        assert_eq!(
+            rows,
+            vec![
+                "demo.mov 10240 64 caffeinesoftware/rewardnights None #340 merged",
+                "recording.mov 6144 64 caffeinesoftware/website None #5 closed",
+                "logs.zip 4096 64 caffeinesoftware/website Some(\"octocat\") #5 closed",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_exports_attachments_whose_files_are_missing() {
+        let temp_dir = TempDir::new("it-exports-attachments-whose-files-are-missing");
+        let output_directory = temp_dir.path();
+        let output_path = output_directory.join("attachments.sqlite");
+
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "export",
+            "fixtures/missing-files",
+            "--output",
+            output_path.to_str().unwrap(),
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(
+                    val.messages,
+                    vec![format!(
+                        "🗃️  Wrote 1 attachment(s) totalling 141 KiB (plus 1 whose file(s) are missing) and 0 parent(s) to `{}`",
+                        output_path.display()
+                    )]
+                );
+                assert_eq!(val.missing_attachments.len(), 1);
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+
+        let connection = rusqlite::Connection::open(&output_path).unwrap();
+        let mut statement = connection
+            .prepare(
+                "SELECT asset_name, size, sha256, missing, parent_url FROM attachments ORDER BY id",
+            )
+            .unwrap();
+        let rows: Vec<String> = statement
+            .query_map([], |row| {
+                Ok(format!(
+                    "{} {:?} {} {} {}",
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<i64>>(1)?,
+                    row.get::<_, Option<String>>(2)?.is_some(),
+                    row.get::<_, bool>(3)?,
+                    row.get::<_, String>(4)?
+                ))
+            })
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+
+        // The missing attachment still gets a row, without a size or hash
+        assert_eq!(
+            rows,
+            vec![
+                "todd-trapani-QldMpmrmWuc-unsplash.jpg Some(144106) true false https://github.com/caffeinesoftware/rewardnights/pull/337",
+                "deleted-recording.mp4 None false true https://github.com/caffeinesoftware/rewardnights/issues/12",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_writes_a_slimmed_archive_from_a_tarball_keeping_release_assets() {
+        let temp_dir =
+            TempDir::new("it-writes-a-slimmed-archive-from-a-tarball-keeping-release-assets");
+        let output_directory = temp_dir.path();
+        let output_path = output_directory.join("slimmed.tar.gz");
+
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "slim",
+            "fixtures/with-releases.tar.gz",
+            "--min-size",
+            "100KiB",
+            "--output",
+            output_path.to_str().unwrap(),
+        ]));
+
+        if let Err(e) = result {
+            panic!("process_attachments returned an error: {}", e)
+        }
+
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            output_path.to_str().unwrap(),
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages, vec![
+                    "rewardnights-linux-amd64.zip (https://github.com/caffeinesoftware/rewardnights/releases/tag/v1.0.0) - 256 KiB",
+                ]);
+                assert!(val.missing_attachments.is_empty());
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_refuses_to_write_a_slimmed_archive_over_the_original() {
+        let temp_dir = TempDir::new("it-refuses-to-write-a-slimmed-archive-over-the-original");
+        let output_directory = temp_dir.path();
+        let archive_path = output_directory.join("with-releases.tar.gz");
+        std::fs::copy("fixtures/with-releases.tar.gz", &archive_path).unwrap();
+
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "slim",
+            archive_path.to_str().unwrap(),
+            "--min-size",
+            "1KiB",
+            "--output",
+            archive_path.to_str().unwrap(),
+        ]));
+
+        match result {
+            Ok(_) => panic!("process_attachments should have returned an error"),
+            Err(e) => assert_eq!(
+                e.to_string(),
+                format!("Can't write the slimmed archive to `{}`, because that's the archive being slimmed. Please write it somewhere else.", archive_path.display())
+            ),
+        }
+
+        // The original archive is left exactly as it was
+        assert_eq!(
+            std::fs::read(&archive_path).unwrap(),
+            std::fs::read("fixtures/with-releases.tar.gz").unwrap()
+        );
+    }
+
+    #[test]
+    fn it_writes_a_remediation_plan_as_json() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "remediate",
+            "fixtures/multiple-repositories",
+            "--min-size",
+            "4KiB",
+            "--format",
+            "json",
+        ]));
+
+        match result {
+            Ok(val) => {
+                let steps: serde_json::Value = serde_json::from_str(&val.messages[0]).unwrap();
+                let steps: Vec<(&str, &str)> = steps
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|step| {
+                        (
+                            step["endpoint"].as_str().unwrap(),
+                            step["text_to_remove"].as_str().unwrap(),
+                        )
+                    })
+                    .collect();
+
+                assert_eq!(steps, vec![
+                    ("repos/caffeinesoftware/website/issues/5", "[recording.mov](https://user-images.githubusercontent.com/845662/200000003-3f1c2a9e-6b1d-4c8e-9f0a-1b2c3d4e5f63.mov)"),
+                    ("repos/caffeinesoftware/rewardnights/pulls/340", "https://user-images.githubusercontent.com/845662/200000001-3f1c2a9e-6b1d-4c8e-9f0a-1b2c3d4e5f61.mov"),
+                    ("repos/caffeinesoftware/website/issues/comments/1001", "[logs.zip](https://user-images.githubusercontent.com/845662/200000002-3f1c2a9e-6b1d-4c8e-9f0a-1b2c3d4e5f62.zip)"),
+                ])
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn it_writes_a_remediation_script() {
+        let result = super::process_attachments(&super::Args::parse_from([
+            "gaaa",
+            "remediate",
+            "fixtures/multiple-repositories",
+            "--min-size",
+            "10KiB",
+        ]));
+
+        match result {
+            Ok(val) => {
+                assert_eq!(val.messages.len(), 1);
+                assert!(val.messages[0].starts_with("#!/usr/bin/env bash"));
+                assert!(val.messages[0].ends_with("# demo.mov (10 KiB) in https://github.com/caffeinesoftware/rewardnights/pull/340\nremove_text 'github.com' PATCH 'repos/caffeinesoftware/rewardnights/pulls/340' 'https://user-images.githubusercontent.com/845662/200000001-3f1c2a9e-6b1d-4c8e-9f0a-1b2c3d4e5f61.mov'"));
+            }
+            Err(e) => {
+                panic!("process_attachments returned an error: {}", e)
+            }
+        }
+    }
+
     #[test]
     fn it_errors_if_expected_files_are_not_present() {
-        let result = super::process_attachments(Some("src".to_string()));
+        let result = super::process_attachments(&super::Args::parse_from(["gaaa", "src"]));
 
         match result {
             Ok(_val) => {
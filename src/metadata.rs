@@ -0,0 +1,73 @@
+use serde::de::{DeserializeOwned, SeqAccess, Visitor};
+use serde::Deserializer;
+use std::fmt;
+use std::io::{BufReader, Error, Read};
+use std::marker::PhantomData;
+
+// Deserializes a JSON array one element at a time, passing each record to `on_record` and
+// counting them as it goes, so we know which entry we were on if something goes wrong
+struct RecordsVisitor<'a, T, F> {
+    index: &'a mut usize,
+    on_record: F,
+    marker: PhantomData<T>,
+}
+
+impl<'de, T: DeserializeOwned, F: FnMut(T)> Visitor<'de> for RecordsVisitor<'_, T, F> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of records")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(record) = seq.next_element::<T>()? {
+            (self.on_record)(record);
+            *self.index += 1;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the records in a metadata file (e.g. `attachments_000001.json`, which contains a JSON
+/// array of attachments) one at a time, passing each one to `on_record`.
+///
+/// If the file can't be parsed, the error names the file, the line and column where parsing
+/// failed, and the index of the entry in the array that we were reading.
+pub fn read_records<T: DeserializeOwned>(
+    reader: impl Read,
+    file_name: &str,
+    on_record: impl FnMut(T),
+) -> Result<(), Error> {
+    let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(reader));
+    let mut index = 0;
+
+    let result = (&mut deserializer)
+        .deserialize_seq(RecordsVisitor {
+            index: &mut index,
+            on_record,
+            marker: PhantomData,
+        })
+        .and_then(|_| deserializer.end());
+
+    match result {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            // serde_json's messages end with the location, which we want to give more prominently
+            let location_suffix = format!(" at line {} column {}", e.line(), e.column());
+            let error_message = e.to_string();
+            let error_message = error_message
+                .strip_suffix(&location_suffix)
+                .unwrap_or(&error_message);
+
+            Err(Error::other(format!(
+                "Could not parse metadata file `{}` at line {}, column {} (entry at index {}): {}",
+                file_name,
+                e.line(),
+                e.column(),
+                index,
+                error_message
+            )))
+        }
+    }
+}
@@ -0,0 +1,519 @@
+use byte_unit::{Byte, UnitType};
+use serde_derive::Serialize;
+use std::io::Error;
+
+use crate::parents::Parent;
+use crate::summary::{DuplicateSet, Group, GroupBy, Grouper, OrphanedFile};
+use crate::{Attachment, SizedAttachment};
+
+/// The formats `gaaa` can print its results in.
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+    Html,
+    Markdown,
+}
+
+impl OutputFormat {
+    /// Whether the format can only be used to list attachments, rather than the summaries printed
+    /// by `--group-by`, `--find-duplicates` and `--find-orphans`.
+    pub fn only_lists_attachments(&self) -> bool {
+        matches!(self, OutputFormat::Html | OutputFormat::Markdown)
+    }
+}
+
+// The shape of each attachment in the JSON output: all of the attachment's metadata, plus where we
+// found it and how big it is
+#[derive(Serialize)]
+struct JsonAttachment<'a> {
+    #[serde(flatten)]
+    attachment: &'a Attachment,
+    path: &'a str,
+    size: u64,
+    human_size: String,
+    parent: Option<&'a Parent>,
+}
+
+// The shape of each duplicate set in the JSON output
+#[derive(Serialize)]
+struct JsonDuplicateSet<'a> {
+    sha256: &'a str,
+    size: u64,
+    human_size: String,
+    copies: usize,
+    wasted_size: u64,
+    human_wasted_size: String,
+    attachments: Vec<JsonDuplicateAttachment<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonDuplicateAttachment<'a> {
+    asset_name: &'a str,
+    parent_url: Option<&'a str>,
+    path: &'a str,
+}
+
+// The header row for the CSV output for duplicates, written ourselves so it's there even when
+// there are no duplicates
+const CSV_DUPLICATE_HEADER: [&str; 5] = ["sha256", "asset_name", "parent_url", "size", "path"];
+
+// The columns in the CSV output for duplicates, one row per attachment in each duplicate set, in
+// the same order as `CSV_DUPLICATE_HEADER`
+#[derive(Serialize)]
+struct CsvDuplicateAttachment<'a> {
+    sha256: &'a str,
+    asset_name: &'a str,
+    parent_url: Option<&'a str>,
+    size: u64,
+    path: &'a str,
+}
+
+// The header row for the CSV output. We write it ourselves, rather than letting `csv` write it
+// along with the first row, so it's there even when there are no attachments.
+const CSV_ATTACHMENT_HEADER: [&str; 10] = [
+    "asset_name",
+    "asset_content_type",
+    "parent_url",
+    "user",
+    "created_at",
+    "size",
+    "path",
+    "parent_title",
+    "parent_state",
+    "parent_updated_at",
+];
+
+// The columns in the CSV output, one row per attachment, in the same order as
+// `CSV_ATTACHMENT_HEADER`
+#[derive(Serialize)]
+struct CsvAttachment<'a> {
+    asset_name: &'a str,
+    asset_content_type: &'a str,
+    parent_url: Option<&'a str>,
+    user: Option<&'a str>,
+    created_at: &'a str,
+    size: u64,
+    path: &'a str,
+    parent_title: Option<&'a str>,
+    parent_state: Option<&'a str>,
+    parent_updated_at: Option<&'a str>,
+}
+
+// The shape of each orphaned file in the JSON output
+#[derive(Serialize)]
+struct JsonOrphanedFile<'a> {
+    path: &'a str,
+    size: u64,
+    human_size: String,
+}
+
+/// Formats a size in bytes using the most appropriate binary unit, e.g. `141 KiB`.
+pub fn format_size(size: u64) -> String {
+    let byte = Byte::from_u64(size);
+    let adjusted_byte = byte.get_appropriate_unit(UnitType::Binary);
+
+    format!("{adjusted_byte:#.0}")
+}
+
+// Describes the issue or pull request an attachment belongs to, e.g. `"Title" (closed, updated
+// 2023-02-10)`, or for attachments in comments and reviews, `comment by octocat on #5 "Title" (...)`
+fn describe_parent(parent: &Parent) -> String {
+    let mut parts: Vec<String> = Vec::new();
+
+    if let Some(comment_kind) = parent.comment_kind {
+        parts.push(comment_kind.to_string());
+
+        if let Some(comment_author) = &parent.comment_author {
+            parts.push(format!("by {}", comment_author));
+        }
+
+        match parent.number {
+            Some(number) => parts.push(format!("on #{}", number)),
+            None => parts.push(format!("on {}", parent.url)),
+        }
+    }
+
+    if let Some(title) = &parent.title {
+        parts.push(format!("\"{}\"", title));
+    }
+
+    match (&parent.state, &parent.updated_at) {
+        (Some(state), Some(updated_at)) => parts.push(format!(
+            "({}, updated {})",
+            state.name(),
+            updated_at.get(..10).unwrap_or(updated_at)
+        )),
+        (Some(state), None) => parts.push(format!("({})", state.name())),
+        _ => {}
+    }
+
+    parts.join(" ")
+}
+
+/// Formats the attachments as one line per attachment, e.g. `image.jpg (<parent URL>) - 141 KiB`.
+/// If we found the attachment's issue or pull request in the archive, we add its title, state and
+/// when it was last updated. Attachments in comments and reviews also say who wrote the comment
+/// or review, and which issue or pull request it's on. Attachments we can't find a parent for are
+/// still listed, with an `unknown parent`.
+pub fn format_as_text(attachments_by_size: &[SizedAttachment]) -> Vec<String> {
+    // Accumulate the messages to print. We do this instead of directly looping and printing messages as
+    // we go becuase it allows us to print warning messages first, before the actual results.
+    attachments_by_size
+        .iter()
+        .fold(Vec::new(), |mut messages, sized_attachment| {
+            let attachment = &sized_attachment.attachment;
+
+            match (attachment.parent_url(), &sized_attachment.parent) {
+                (Some(parent_url), Some(parent)) => messages.push(format!(
+                    "{} ({}) - {} - {}",
+                    attachment.asset_name,
+                    parent_url,
+                    format_size(sized_attachment.size),
+                    describe_parent(parent)
+                )),
+                (Some(parent_url), None) => messages.push(format!(
+                    "{} ({}) - {}",
+                    attachment.asset_name,
+                    parent_url,
+                    format_size(sized_attachment.size)
+                )),
+                (None, _) => {
+                    eprintln!("⚠️ Could not find the issue, pull request, comment, review or release for attachment {}", attachment.asset_name);
+                    messages.push(format!(
+                        "{} (unknown parent) - {}",
+                        attachment.asset_name,
+                        format_size(sized_attachment.size)
+                    ))
+                }
+            }
+
+            messages
+        })
+}
+
+/// Formats the attachments as a pretty-printed JSON array, returned as a single message.
+pub fn format_as_json(attachments_by_size: &[SizedAttachment]) -> Result<Vec<String>, Error> {
+    let json_attachments: Vec<JsonAttachment> = attachments_by_size
+        .iter()
+        .map(|sized_attachment| JsonAttachment {
+            attachment: &sized_attachment.attachment,
+            path: &sized_attachment.path,
+            size: sized_attachment.size,
+            human_size: format_size(sized_attachment.size),
+            parent: sized_attachment.parent.as_ref(),
+        })
+        .collect();
+
+    Ok(vec![serde_json::to_string_pretty(&json_attachments)?])
+}
+
+/// Formats the attachments as CSV with a header row, returned as a single message.
+pub fn format_as_csv(attachments_by_size: &[SizedAttachment]) -> Result<Vec<String>, Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(Vec::new());
+
+    writer.write_record(CSV_ATTACHMENT_HEADER)?;
+
+    for sized_attachment in attachments_by_size {
+        let attachment = &sized_attachment.attachment;
+        let parent = sized_attachment.parent.as_ref();
+
+        writer.serialize(CsvAttachment {
+            asset_name: &attachment.asset_name,
+            asset_content_type: &attachment.asset_content_type,
+            parent_url: attachment.parent_url(),
+            user: attachment.user.as_deref(),
+            created_at: &attachment.created_at,
+            size: sized_attachment.size,
+            path: &sized_attachment.path,
+            parent_title: parent.and_then(|parent| parent.title.as_deref()),
+            parent_state: parent
+                .and_then(|parent| parent.state.as_ref())
+                .map(|state| state.name()),
+            parent_updated_at: parent.and_then(|parent| parent.updated_at.as_deref()),
+        })?;
+    }
+
+    let csv = writer.into_inner().map_err(|e| e.into_error())?;
+
+    // `println!` adds the final newline back when we print the message
+    Ok(vec![String::from_utf8_lossy(&csv).trim_end().to_string()])
+}
+
+// Escapes the characters which would otherwise be treated as Markdown formatting
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for character in text.chars() {
+        if matches!(
+            character,
+            '\\' | '|' | '[' | ']' | '*' | '_' | '`' | '<' | '>' | '#' | '~'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(character);
+    }
+
+    escaped
+}
+
+/// Formats the attachments as Markdown for pasting into a tracking issue, returned as a single
+/// message. There's a heading for each repository, largest first, followed by a task list with an
+/// item for each attachment, which GitHub shows as a checkbox to tick off once it has been dealt
+/// with. (GitHub only shows checkboxes in lists, not tables.)
+pub fn format_as_markdown(attachments_by_size: &[SizedAttachment]) -> Vec<String> {
+    let mut grouper = Grouper::new(GroupBy::Repository);
+    for sized_attachment in attachments_by_size {
+        grouper.add(sized_attachment);
+    }
+
+    let sections: Vec<String> = grouper
+        .into_groups()
+        .iter()
+        .map(|group| {
+            let mut section = format!(
+                "## {}\n\n{} attachment(s) totalling {}\n",
+                escape_markdown(&group.name),
+                group.count,
+                format_size(group.size)
+            );
+
+            // The attachments are already sorted by size, so each list is too
+            for sized_attachment in attachments_by_size.iter().filter(|sized_attachment| {
+                GroupBy::Repository.group_name_or_unknown(sized_attachment) == group.name
+            }) {
+                let attachment = &sized_attachment.attachment;
+
+                section.push_str(&format!(
+                    "\n- [ ] {} — {} — {}",
+                    escape_markdown(&attachment.asset_name),
+                    format_size(sized_attachment.size),
+                    // GitHub turns links to issues, pull requests and comments into references
+                    // showing their titles, so we can just use the bare URL
+                    attachment.parent_url().unwrap_or("unknown parent")
+                ));
+            }
+
+            section
+        })
+        .collect();
+
+    vec![sections.join("\n\n")]
+}
+
+/// Formats groups of attachments as one line per group, e.g. `owner/repo - 3 attachment(s) - 1 MiB`.
+pub fn format_groups_as_text(groups: &[Group]) -> Vec<String> {
+    groups
+        .iter()
+        .map(|group| {
+            format!(
+                "{} - {} attachment(s) - {}",
+                group.name,
+                group.count,
+                format_size(group.size)
+            )
+        })
+        .collect()
+}
+
+/// Formats groups of attachments as a pretty-printed JSON array, returned as a single message.
+pub fn format_groups_as_json(groups: &[Group], group_by: &GroupBy) -> Result<Vec<String>, Error> {
+    let json_groups: Vec<serde_json::Value> = groups
+        .iter()
+        .map(|group| {
+            let mut json_group = serde_json::Map::new();
+            json_group.insert(group_by.key_name().to_string(), group.name.clone().into());
+            json_group.insert("count".to_string(), group.count.into());
+            json_group.insert("size".to_string(), group.size.into());
+            json_group.insert("human_size".to_string(), format_size(group.size).into());
+
+            if *group_by == GroupBy::User {
+                json_group.insert("bot".to_string(), group.is_bot().into());
+            }
+
+            serde_json::Value::Object(json_group)
+        })
+        .collect();
+
+    Ok(vec![serde_json::to_string_pretty(&json_groups)?])
+}
+
+/// Formats groups of attachments as CSV with a header row, returned as a single message.
+pub fn format_groups_as_csv(groups: &[Group], group_by: &GroupBy) -> Result<Vec<String>, Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+
+    let mut header = vec![group_by.key_name(), "count", "size"];
+    if *group_by == GroupBy::User {
+        header.push("bot");
+    }
+    writer.write_record(header)?;
+
+    for group in groups {
+        let mut record = vec![
+            group.name.clone(),
+            group.count.to_string(),
+            group.size.to_string(),
+        ];
+        if *group_by == GroupBy::User {
+            record.push(group.is_bot().to_string());
+        }
+        writer.write_record(record)?;
+    }
+
+    let csv = writer.into_inner().map_err(|e| e.into_error())?;
+
+    // `println!` adds the final newline back when we print the message
+    Ok(vec![String::from_utf8_lossy(&csv).trim_end().to_string()])
+}
+
+/// Formats duplicate sets as a heading line per set, followed by an indented line for each copy,
+/// with a final line totalling up the wasted space.
+pub fn format_duplicates_as_text(duplicate_sets: &[DuplicateSet]) -> Vec<String> {
+    let mut messages: Vec<String> = Vec::new();
+
+    for duplicate_set in duplicate_sets {
+        messages.push(format!(
+            "{} copies of {} - {} each, {} wasted",
+            duplicate_set.attachments.len(),
+            duplicate_set.attachments[0].attachment.asset_name,
+            format_size(duplicate_set.size),
+            format_size(duplicate_set.wasted_size())
+        ));
+
+        for sized_attachment in &duplicate_set.attachments {
+            let attachment = &sized_attachment.attachment;
+            messages.push(format!(
+                "  {} ({})",
+                attachment.asset_name,
+                attachment.parent_url().unwrap_or("unknown parent")
+            ));
+        }
+    }
+
+    if !duplicate_sets.is_empty() {
+        let wasted_size: u64 = duplicate_sets
+            .iter()
+            .map(|duplicate_set| duplicate_set.wasted_size())
+            .sum();
+        messages.push(format!(
+            "♻️ Removing duplicates would save {}",
+            format_size(wasted_size)
+        ));
+    }
+
+    messages
+}
+
+/// Formats duplicate sets as a pretty-printed JSON array, returned as a single message.
+pub fn format_duplicates_as_json(duplicate_sets: &[DuplicateSet]) -> Result<Vec<String>, Error> {
+    let json_duplicate_sets: Vec<JsonDuplicateSet> = duplicate_sets
+        .iter()
+        .map(|duplicate_set| JsonDuplicateSet {
+            sha256: &duplicate_set.sha256,
+            size: duplicate_set.size,
+            human_size: format_size(duplicate_set.size),
+            copies: duplicate_set.attachments.len(),
+            wasted_size: duplicate_set.wasted_size(),
+            human_wasted_size: format_size(duplicate_set.wasted_size()),
+            attachments: duplicate_set
+                .attachments
+                .iter()
+                .map(|sized_attachment| JsonDuplicateAttachment {
+                    asset_name: &sized_attachment.attachment.asset_name,
+                    parent_url: sized_attachment.attachment.parent_url(),
+                    path: &sized_attachment.path,
+                })
+                .collect(),
+        })
+        .collect();
+
+    Ok(vec![serde_json::to_string_pretty(&json_duplicate_sets)?])
+}
+
+/// Formats duplicate sets as CSV with a header row, returned as a single message.
+pub fn format_duplicates_as_csv(duplicate_sets: &[DuplicateSet]) -> Result<Vec<String>, Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(Vec::new());
+
+    writer.write_record(CSV_DUPLICATE_HEADER)?;
+
+    for duplicate_set in duplicate_sets {
+        for sized_attachment in &duplicate_set.attachments {
+            writer.serialize(CsvDuplicateAttachment {
+                sha256: &duplicate_set.sha256,
+                asset_name: &sized_attachment.attachment.asset_name,
+                parent_url: sized_attachment.attachment.parent_url(),
+                size: sized_attachment.size,
+                path: &sized_attachment.path,
+            })?;
+        }
+    }
+
+    let csv = writer.into_inner().map_err(|e| e.into_error())?;
+
+    // `println!` adds the final newline back when we print the message
+    Ok(vec![String::from_utf8_lossy(&csv).trim_end().to_string()])
+}
+
+/// Formats orphaned files as one line per file, e.g. `attachments/<uuid>/image.jpg - 141 KiB`, with
+/// a final line totalling up their size.
+pub fn format_orphans_as_text(orphaned_files: &[OrphanedFile]) -> Vec<String> {
+    let mut messages: Vec<String> = orphaned_files
+        .iter()
+        .map(|orphaned_file| {
+            format!(
+                "{} - {}",
+                orphaned_file.path,
+                format_size(orphaned_file.size)
+            )
+        })
+        .collect();
+
+    if !orphaned_files.is_empty() {
+        let orphaned_size: u64 = orphaned_files
+            .iter()
+            .map(|orphaned_file| orphaned_file.size)
+            .sum();
+        messages.push(format!(
+            "🧹 {} orphaned file(s) totalling {} aren't used by any issue, pull request or release",
+            orphaned_files.len(),
+            format_size(orphaned_size)
+        ));
+    }
+
+    messages
+}
+
+/// Formats orphaned files as a pretty-printed JSON array, returned as a single message.
+pub fn format_orphans_as_json(orphaned_files: &[OrphanedFile]) -> Result<Vec<String>, Error> {
+    let json_orphaned_files: Vec<JsonOrphanedFile> = orphaned_files
+        .iter()
+        .map(|orphaned_file| JsonOrphanedFile {
+            path: &orphaned_file.path,
+            size: orphaned_file.size,
+            human_size: format_size(orphaned_file.size),
+        })
+        .collect();
+
+    Ok(vec![serde_json::to_string_pretty(&json_orphaned_files)?])
+}
+
+/// Formats orphaned files as CSV with a header row, returned as a single message.
+pub fn format_orphans_as_csv(orphaned_files: &[OrphanedFile]) -> Result<Vec<String>, Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+
+    writer.write_record(["path", "size"])?;
+
+    for orphaned_file in orphaned_files {
+        writer.write_record([orphaned_file.path.clone(), orphaned_file.size.to_string()])?;
+    }
+
+    let csv = writer.into_inner().map_err(|e| e.into_error())?;
+
+    // `println!` adds the final newline back when we print the message
+    Ok(vec![String::from_utf8_lossy(&csv).trim_end().to_string()])
+}
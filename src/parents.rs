@@ -0,0 +1,305 @@
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+
+use crate::archive::{self, Archive};
+use crate::{login_from_user_url, metadata, Attachment};
+
+/// Whether an issue or pull request is still open.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParentState {
+    Open,
+    Closed,
+    Merged,
+}
+
+impl ParentState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ParentState::Open => "open",
+            ParentState::Closed => "closed",
+            ParentState::Merged => "merged",
+        }
+    }
+}
+
+/// The issue or pull request that an attachment belongs to (for attachments in comments and
+/// reviews, the one the comment or review is on), with details from the archive's `issues_*.json`
+/// and `pull_requests_*.json` files, and the comment and review metadata files.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Parent {
+    pub url: String,
+    pub number: Option<u64>,
+    pub title: Option<String>,
+    pub state: Option<ParentState>,
+    pub updated_at: Option<String>,
+    /// If the attachment is in a comment or review, rather than the issue or pull request itself,
+    /// what kind it is, e.g. `review comment`
+    #[serde(skip)]
+    pub comment_kind: Option<&'static str>,
+    /// The login of the user who wrote the comment or review the attachment is in
+    pub comment_author: Option<String>,
+}
+
+// The metadata files for the issues and pull requests that attachments belong to
+const ISSUE_MODEL_NAMES: [&str; 2] = ["issues", "pull_requests"];
+
+// The metadata files for the comments and reviews that attachments can be in
+const COMMENT_MODEL_NAMES: [&str; 3] = [
+    "issue_comments",
+    "pull_request_review_comments",
+    "pull_request_reviews",
+];
+
+// The fields we need from each record in `issue_comments_*.json`, `pull_request_reviews_*.json`
+// and `pull_request_review_comments_*.json`
+#[derive(Deserialize, Clone)]
+struct CommentRecord {
+    url: String,
+    issue: Option<String>,
+    pull_request: Option<String>,
+    user: Option<String>,
+}
+
+// The fields we need from each record in `issues_*.json` and `pull_requests_*.json`
+#[derive(Deserialize, Clone)]
+struct IssueRecord {
+    url: String,
+    title: Option<String>,
+    closed_at: Option<String>,
+    // Only set for pull requests
+    merged_at: Option<String>,
+    updated_at: Option<String>,
+}
+
+impl IssueRecord {
+    fn state(&self) -> ParentState {
+        if self.merged_at.is_some() {
+            ParentState::Merged
+        } else if self.closed_at.is_some() {
+            ParentState::Closed
+        } else {
+            ParentState::Open
+        }
+    }
+}
+
+/// The issue, pull request, comment and review records needed to look up attachments' parents,
+/// collected while a tarball is first read so we don't have to go through it again.
+#[derive(Default)]
+pub struct ParentRecords {
+    issues: Vec<IssueRecord>,
+    comments: Vec<CommentRecord>,
+}
+
+impl ParentRecords {
+    /// Reads the records from an entry in the archive if it's one of the issue, pull request,
+    /// comment or review metadata files, returning whether it was.
+    pub fn read_metadata_file(&mut self, entry_path: &str, reader: impl Read) -> bool {
+        if ISSUE_MODEL_NAMES
+            .iter()
+            .any(|model_name| archive::is_metadata_filename(entry_path, model_name))
+        {
+            read_optional_records(reader, entry_path, |issue| self.issues.push(issue));
+            true
+        } else if COMMENT_MODEL_NAMES
+            .iter()
+            .any(|model_name| archive::is_metadata_filename(entry_path, model_name))
+        {
+            read_optional_records(reader, entry_path, |comment| self.comments.push(comment));
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// Reads the records from a single metadata file. These files only add detail to the results, so
+// if one can't be read, we warn and carry on without it.
+fn read_optional_records<T: DeserializeOwned>(
+    reader: impl Read,
+    file_name: &str,
+    on_record: impl FnMut(T),
+) {
+    eprintln!("Reading metadata file {}", file_name);
+
+    if let Err(e) = metadata::read_records(reader, file_name, on_record) {
+        eprintln!("⚠️ {}. Skipping this file...", e);
+    }
+}
+
+// Passes the records for the given models to `on_record`. If they were collected when the archive
+// was opened, we use those, and otherwise we read the archive's metadata files.
+fn for_each_record<T: DeserializeOwned>(
+    archive: &Archive,
+    model_names: &[&str],
+    collected_records: impl FnOnce(&ParentRecords) -> &[T],
+    mut on_record: impl FnMut(&T),
+) {
+    if let Some(parent_records) = archive.parent_records() {
+        collected_records(parent_records).iter().for_each(on_record);
+        return;
+    }
+
+    let result = archive.for_each_metadata_file(model_names, |file_name, reader| {
+        read_optional_records(reader, file_name, |record: T| on_record(&record));
+        Ok(())
+    });
+
+    if let Err(e) = result {
+        eprintln!(
+            "⚠️ Could not read {} metadata files: {}. Skipping...",
+            model_names.join(", "),
+            e
+        );
+    }
+}
+
+// Returns the URL of the comment or review an attachment is in, and what kind it is, unless it's
+// directly on an issue or pull request
+fn find_comment(attachment: &Attachment) -> Option<(&str, &'static str)> {
+    if attachment.pull_request.is_some() || attachment.issue.is_some() {
+        return None;
+    }
+
+    [
+        (&attachment.issue_comment, "comment"),
+        (&attachment.pull_request_review_comment, "review comment"),
+        (&attachment.pull_request_review, "review"),
+    ]
+    .into_iter()
+    .find_map(|(url, kind)| url.as_deref().map(|url| (url, kind)))
+}
+
+// Returns the issue or pull request number from the end of its URL, e.g. `5` for
+// `https://github.com/owner/repo/issues/5`
+fn number_from_url(url: &str) -> Option<u64> {
+    url.rsplit('/').next()?.parse().ok()
+}
+
+/// Looks up the issue or pull request that each attachment belongs to (for attachments in
+/// comments, the one the comment is on, plus the comment's author), and sets the `parent` it's
+/// paired with.
+pub fn resolve_parents<'a>(
+    archive: &Archive,
+    attachments: impl IntoIterator<Item = (&'a Attachment, &'a mut Option<Parent>)>,
+) {
+    let mut attachments: Vec<(&Attachment, &mut Option<Parent>)> =
+        attachments.into_iter().collect();
+
+    let comment_urls: HashSet<String> = attachments
+        .iter()
+        .filter_map(|(attachment, _)| find_comment(attachment))
+        .map(|(comment_url, _)| comment_url.to_string())
+        .collect();
+
+    let mut comments: HashMap<String, CommentRecord> = HashMap::new();
+
+    if !comment_urls.is_empty() {
+        eprintln!("💬 Looking up the issues and pull requests that comments belong to...");
+
+        for_each_record(
+            archive,
+            &COMMENT_MODEL_NAMES,
+            |parent_records| &parent_records.comments,
+            |comment: &CommentRecord| {
+                if comment_urls.contains(&comment.url) {
+                    comments.insert(comment.url.clone(), comment.clone());
+                }
+            },
+        );
+    }
+
+    // A comment or review's URL is its parent's URL with a fragment (e.g. `#issuecomment-<id>`) on
+    // the end, so we can fall back to that if it isn't in the archive
+    let comment_parent_urls: HashMap<&str, String> = comment_urls
+        .iter()
+        .map(|comment_url| {
+            let parent_url = comments
+                .get(comment_url)
+                .and_then(|comment| comment.issue.clone().or(comment.pull_request.clone()))
+                .unwrap_or_else(|| {
+                    comment_url
+                        .split_once('#')
+                        .map_or(comment_url.as_str(), |(parent_url, _)| parent_url)
+                        .to_string()
+                });
+
+            (comment_url.as_str(), parent_url)
+        })
+        .collect();
+
+    let mut needed_parent_urls: HashSet<&str> = attachments
+        .iter()
+        .filter_map(|(attachment, _)| {
+            attachment
+                .pull_request
+                .as_deref()
+                .or(attachment.issue.as_deref())
+        })
+        .collect();
+    needed_parent_urls.extend(comment_parent_urls.values().map(|url| url.as_str()));
+
+    if needed_parent_urls.is_empty() {
+        return;
+    }
+
+    eprintln!("🏷️  Looking up issue and pull request titles...");
+
+    let mut issues: HashMap<String, IssueRecord> = HashMap::new();
+    for_each_record(
+        archive,
+        &ISSUE_MODEL_NAMES,
+        |parent_records| &parent_records.issues,
+        |issue: &IssueRecord| {
+            if needed_parent_urls.contains(issue.url.as_str()) {
+                issues.insert(issue.url.clone(), issue.clone());
+            }
+        },
+    );
+
+    for (attachment, parent) in attachments.iter_mut() {
+        let direct_parent_url = attachment
+            .pull_request
+            .as_deref()
+            .or(attachment.issue.as_deref());
+
+        let (parent_url, comment_kind, comment) =
+            match (direct_parent_url, find_comment(attachment)) {
+                (Some(parent_url), _) => (parent_url.to_string(), None, None),
+                (None, Some((comment_url, comment_kind))) => {
+                    match comment_parent_urls.get(comment_url) {
+                        Some(parent_url) => (
+                            parent_url.clone(),
+                            Some(comment_kind),
+                            comments.get(comment_url),
+                        ),
+                        None => continue,
+                    }
+                }
+                (None, None) => continue,
+            };
+        let issue = issues.get(&parent_url);
+
+        // We only know more than the URL already tells us about attachments directly on an issue
+        // or pull request if it's in the archive
+        if issue.is_none() && comment_kind.is_none() {
+            continue;
+        }
+
+        **parent = Some(Parent {
+            number: number_from_url(&parent_url),
+            title: issue.and_then(|issue| issue.title.clone()),
+            state: issue.map(|issue| issue.state()),
+            updated_at: issue.and_then(|issue| issue.updated_at.clone()),
+            comment_kind,
+            comment_author: comment
+                .and_then(|comment| comment.user.as_deref())
+                .and_then(login_from_user_url)
+                .map(|login| login.to_string()),
+            url: parent_url,
+        });
+    }
+}
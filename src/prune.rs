@@ -0,0 +1,299 @@
+use serde_derive::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Error;
+use std::path::Path;
+
+use crate::archive::Archive;
+use crate::metadata;
+use crate::output::format_size;
+use crate::SizedAttachment;
+
+/// What a removed attachment's URL is replaced with, unless the user gives their own placeholder.
+pub const DEFAULT_PLACEHOLDER: &str = "#attachment-removed";
+
+// The metadata files whose records have Markdown bodies that can link to attachments
+const MODEL_NAMES: [&str; 7] = [
+    "issues",
+    "pull_requests",
+    "issue_comments",
+    "pull_request_reviews",
+    "pull_request_review_comments",
+    "commit_comments",
+    "releases",
+];
+
+// The only fields we need from each record to find links to attachments
+#[derive(Deserialize)]
+struct Record {
+    url: Option<String>,
+    body: Option<String>,
+}
+
+/// A link to an attachment in the body of an issue, pull request, comment, review or release.
+pub struct Link<'a> {
+    pub sized_attachment: &'a SizedAttachment,
+    /// The URL of the issue, pull request, comment, review or release with the link in its body
+    pub record_url: String,
+    /// The Markdown (or HTML) in the body that links to the attachment, e.g.
+    /// `![screenshot](<attachment URL>)`
+    pub text: String,
+}
+
+// Release assets are never linked to from Markdown, so we only look for attachments
+fn linkable_attachments(attachments_by_size: &[SizedAttachment]) -> Vec<&SizedAttachment> {
+    attachments_by_size
+        .iter()
+        .filter(|sized_attachment| !sized_attachment.attachment.is_release_asset())
+        .collect()
+}
+
+/// Finds every link to `attachments_by_size` in the bodies of the archive's issues, pull requests,
+/// comments, reviews and releases.
+pub fn find_links<'a>(
+    archive: &Archive,
+    attachments_by_size: &'a [SizedAttachment],
+) -> Result<Vec<Link<'a>>, Error> {
+    let attachments = linkable_attachments(attachments_by_size);
+    let mut links: Vec<Link> = Vec::new();
+
+    if attachments.is_empty() {
+        return Ok(links);
+    }
+
+    archive.for_each_metadata_file(&MODEL_NAMES, |file_name, reader| {
+        eprintln!("Reading metadata file {}", file_name);
+
+        metadata::read_records(reader, file_name, |record: Record| {
+            let body = record.body.unwrap_or_default();
+            let record_url = record.url.unwrap_or_else(|| file_name.to_string());
+
+            for sized_attachment in &attachments {
+                let url = &sized_attachment.attachment.url;
+                let mut texts: Vec<&str> = Vec::new();
+
+                for (start, _) in body.match_indices(url.as_str()) {
+                    let text = link_text(&body, start, url);
+
+                    if !texts.contains(&text) {
+                        texts.push(text);
+                    }
+                }
+
+                for text in texts {
+                    links.push(Link {
+                        sized_attachment,
+                        record_url: record_url.clone(),
+                        text: text.to_string(),
+                    });
+                }
+            }
+        })
+    })?;
+
+    Ok(links)
+}
+
+// Returns the Markdown image or link (e.g. `![screenshot](<url>)`, or `[![screenshot](<thumbnail
+// url>)](<url>)` for an image which links to the attachment) or HTML tag (e.g. `<img src="<url>">`,
+// or `<a href="<url>">logs</a>` including the link's text) around the attachment URL at `start` in
+// `body`, or just the URL if it isn't part of any of them
+fn link_text<'a>(body: &'a str, start: usize, url: &'a str) -> &'a str {
+    let end = start + url.len();
+    let before = &body[..start];
+    let after = &body[end..];
+
+    if before.ends_with("](") && after.starts_with(')') {
+        if let Some(open) = matching_open_bracket(&before[..before.len() - 1]) {
+            let open = if before[..open].ends_with('!') {
+                open - 1
+            } else {
+                open
+            };
+
+            return &body[open..end + 1];
+        }
+    }
+
+    if let Some(open) = before.rfind('<') {
+        if !before[open..].contains('>') {
+            if let Some(close) = after.find('>') {
+                let tag_end = end + close + 1;
+                let tag = &body[open..tag_end];
+
+                // Links have text (and a closing tag) which would be left behind on its own
+                let is_link =
+                    tag[1..].starts_with(['a', 'A']) && tag[2..].starts_with(char::is_whitespace);
+
+                if !is_link {
+                    return tag;
+                }
+
+                if let Some(closing_tag) = body[tag_end..].to_ascii_lowercase().find("</a>") {
+                    return &body[open..tag_end + closing_tag + "</a>".len()];
+                }
+            }
+        }
+    }
+
+    url
+}
+
+// Returns where the `[` matching the `]` at the end of `text` is, skipping over any images or links
+// nested inside it, or `None` if there isn't one on the same line
+fn matching_open_bracket(text: &str) -> Option<usize> {
+    let mut depth = 0;
+
+    for (index, character) in text.char_indices().rev() {
+        match character {
+            ']' => depth += 1,
+            '[' => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return Some(index);
+                }
+            }
+            '\n' => return None,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Replaces links to `attachments_by_size` in the bodies of the archive's issues, pull requests,
+/// comments, reviews and releases with `placeholder`, writing modified copies of the metadata
+/// files that had links in them to `output_directory`.
+///
+/// Returns a message for each record whose links were replaced, followed by a summary.
+pub fn rewrite_metadata_files(
+    archive: &Archive,
+    attachments_by_size: &[SizedAttachment],
+    placeholder: &str,
+    output_directory: &Path,
+) -> Result<Vec<String>, Error> {
+    let attachments = linkable_attachments(attachments_by_size);
+
+    // The placeholder is written straight into the JSON, so it needs to be escaped like a string
+    let escaped_placeholder = serde_json::to_string(placeholder)?;
+    let escaped_placeholder = &escaped_placeholder[1..escaped_placeholder.len() - 1];
+
+    let mut messages: Vec<String> = Vec::new();
+    let mut pruned_urls: HashSet<&str> = HashSet::new();
+    let mut links_count = 0;
+    let mut written_files_count = 0;
+
+    if !attachments.is_empty() {
+        fs::create_dir_all(output_directory)?;
+
+        archive.for_each_metadata_file(&MODEL_NAMES, |file_name, reader| {
+            eprintln!("Reading metadata file {}", file_name);
+
+            let mut contents = String::new();
+            reader.read_to_string(&mut contents)?;
+
+            // Work out which attachments each record links to, then replace their URLs in the
+            // file as a whole, so everything else is written back out exactly as we found it
+            let mut linked_attachments: Vec<(&SizedAttachment, String)> = Vec::new();
+
+            metadata::read_records(contents.as_bytes(), file_name, |record: Record| {
+                let body = record.body.unwrap_or_default();
+
+                for sized_attachment in &attachments {
+                    if body.contains(&sized_attachment.attachment.url) {
+                        let record_url = record.url.as_deref().unwrap_or(file_name);
+                        linked_attachments.push((sized_attachment, record_url.to_string()));
+                    }
+                }
+            })?;
+
+            // The bodies have been decoded from JSON, so a URL that's only written with escaped
+            // characters won't be in the file as it's written, and there's nothing to replace.
+            // We count each URL before replacing any, since several records can link to it.
+            let mut replacements: Vec<(&str, usize)> = Vec::new();
+
+            for (sized_attachment, record_url) in linked_attachments {
+                let attachment = &sized_attachment.attachment;
+                let url = attachment.url.as_str();
+
+                let count = match replacements
+                    .iter()
+                    .find(|(replaced_url, _)| *replaced_url == url)
+                {
+                    Some((_, count)) => *count,
+                    None => {
+                        let count = contents.matches(url).count();
+                        replacements.push((url, count));
+                        count
+                    }
+                };
+
+                if count > 0 {
+                    messages.push(format!(
+                        "Removed {} ({}) from {}",
+                        attachment.asset_name,
+                        format_size(sized_attachment.size),
+                        record_url
+                    ));
+                }
+            }
+
+            let mut modified = false;
+
+            for (url, count) in replacements {
+                if count > 0 {
+                    contents = contents.replace(url, escaped_placeholder);
+                    pruned_urls.insert(url);
+                    links_count += count;
+                    modified = true;
+                }
+            }
+
+            if modified {
+                let output_path = output_directory.join(file_name);
+                eprintln!("📝 Writing {}", output_path.display());
+
+                fs::write(output_path, &contents)?;
+                written_files_count += 1;
+            }
+
+            Ok(())
+        })?;
+    }
+
+    for sized_attachment in &attachments {
+        let attachment = &sized_attachment.attachment;
+
+        if !pruned_urls.contains(attachment.url.as_str()) {
+            eprintln!(
+                "⚠️ Could not find a link to attachment {} ({}) in any issue, pull request, comment, review or release. Skipping...",
+                attachment.asset_name,
+                attachment.parent_url().unwrap_or("unknown parent")
+            );
+        }
+    }
+
+    if messages.is_empty() {
+        eprintln!("🎉 No links to attachments needed replacing");
+    } else {
+        let pruned_size: u64 = attachments
+            .iter()
+            .filter(|sized_attachment| {
+                pruned_urls.contains(sized_attachment.attachment.url.as_str())
+            })
+            .map(|sized_attachment| sized_attachment.size)
+            .sum();
+
+        messages.push(format!(
+            "✂️ Replaced {} link(s) to {} attachment(s) totalling {}, and wrote {} modified metadata file(s) to `{}`",
+            links_count,
+            pruned_urls.len(),
+            format_size(pruned_size),
+            written_files_count,
+            output_directory.display()
+        ));
+    }
+
+    Ok(messages)
+}
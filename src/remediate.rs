@@ -0,0 +1,157 @@
+use serde_derive::Serialize;
+use std::io::Error;
+
+use crate::output::format_size;
+use crate::prune::Link;
+
+/// The formats `gaaa remediate` can write its remediation plan in.
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq)]
+pub enum RemediationFormat {
+    /// A Bash script which uses `gh api` to remove each link
+    Script,
+    /// A JSON array with the API request needed to remove each link
+    Json,
+}
+
+// Downloads the current body from the API (in case it has changed since the archive was made),
+// removes every copy of the text and writes the body back
+const SCRIPT_PREAMBLE: &str = r#"#!/usr/bin/env bash
+# Removes links to large attachments from issues, pull requests, comments and reviews on GitHub.
+# Generated by gaaa - review it before running it!
+set -euo pipefail
+
+remove_text() {
+  local hostname="$1" method="$2" endpoint="$3" text="$4" body
+  body="$(gh api --hostname "$hostname" "$endpoint" --jq .body)"
+  gh api --hostname "$hostname" --method "$method" "$endpoint" -f body="${body//"$text"/}" > /dev/null
+}
+"#;
+
+// The shape of each step in the JSON remediation plan
+#[derive(Serialize)]
+struct RemediationStep<'a> {
+    hostname: &'a str,
+    method: &'static str,
+    endpoint: String,
+    html_url: &'a str,
+    asset_name: &'a str,
+    size: u64,
+    human_size: String,
+    text_to_remove: &'a str,
+}
+
+// The REST API request which edits the body of an issue, pull request, comment or review
+struct ApiRequest<'a> {
+    hostname: &'a str,
+    method: &'static str,
+    endpoint: String,
+}
+
+// Works out which REST API request edits the body of an issue, pull request, comment or review
+// from its URL, e.g. `PATCH repos/owner/repo/issues/comments/1001` for
+// `https://github.com/owner/repo/issues/5#issuecomment-1001`
+fn api_request(record_url: &str) -> Option<ApiRequest<'_>> {
+    let (path, fragment) = match record_url.split_once('#') {
+        Some((path, fragment)) => (path, fragment),
+        None => (record_url, ""),
+    };
+    let mut path_segments = path.split_once("://")?.1.split('/');
+
+    let hostname = path_segments.next()?;
+    let owner = path_segments.next()?;
+    let repo = path_segments.next()?;
+    let kind = path_segments.next()?;
+    let number = path_segments.next()?;
+    let repository = format!("repos/{}/{}", owner, repo);
+
+    let (method, endpoint) = if let Some(id) = fragment.strip_prefix("issuecomment-") {
+        ("PATCH", format!("{}/issues/comments/{}", repository, id))
+    } else if let Some(id) = fragment.strip_prefix("discussion_r") {
+        ("PATCH", format!("{}/pulls/comments/{}", repository, id))
+    } else if let Some(id) = fragment.strip_prefix("pullrequestreview-") {
+        (
+            "PUT",
+            format!("{}/pulls/{}/reviews/{}", repository, number, id),
+        )
+    } else if let Some(id) = fragment.strip_prefix("commitcomment-") {
+        ("PATCH", format!("{}/comments/{}", repository, id))
+    } else {
+        match (kind, fragment) {
+            ("issues", "") => ("PATCH", format!("{}/issues/{}", repository, number)),
+            ("pull", "") => ("PATCH", format!("{}/pulls/{}", repository, number)),
+            // Releases can only be edited by their ID, which isn't in their URLs
+            _ => return None,
+        }
+    };
+
+    Some(ApiRequest {
+        hostname,
+        method,
+        endpoint,
+    })
+}
+
+// Quotes a string so Bash passes it through exactly as it is
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+// Pairs each link with the API request which edits its issue, pull request, comment or review,
+// warning about any we can't work out a request for
+fn links_with_requests<'a, 'b>(links: &'b [Link<'a>]) -> Vec<(&'b Link<'a>, ApiRequest<'b>)> {
+    links
+        .iter()
+        .filter_map(|link| match api_request(&link.record_url) {
+            Some(api_request) => Some((link, api_request)),
+            None => {
+                eprintln!(
+                    "⚠️ Could not work out the API endpoint for {}, so can't remove its link to {}. Skipping...",
+                    link.record_url, link.sized_attachment.attachment.asset_name
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+/// Formats the links as a Bash script which removes each one using `gh api`, returned as a single
+/// message.
+pub fn format_as_script(links: &[Link]) -> Vec<String> {
+    let mut script = SCRIPT_PREAMBLE.to_string();
+
+    for (link, api_request) in links_with_requests(links) {
+        script.push_str(&format!(
+            "\n# {} ({}) in {}\nremove_text {} {} {} {}\n",
+            link.sized_attachment.attachment.asset_name,
+            format_size(link.sized_attachment.size),
+            link.record_url,
+            shell_quote(api_request.hostname),
+            api_request.method,
+            shell_quote(&api_request.endpoint),
+            shell_quote(&link.text)
+        ));
+    }
+
+    // `println!` adds the final newline back when we print the message
+    vec![script.trim_end().to_string()]
+}
+
+/// Formats the links as a pretty-printed JSON array with the API request needed to remove each
+/// one, returned as a single message.
+pub fn format_as_json(links: &[Link]) -> Result<Vec<String>, Error> {
+    let steps: Vec<RemediationStep> = links_with_requests(links)
+        .into_iter()
+        .map(|(link, api_request)| RemediationStep {
+            hostname: api_request.hostname,
+            method: api_request.method,
+            endpoint: api_request.endpoint,
+            html_url: &link.record_url,
+            asset_name: &link.sized_attachment.attachment.asset_name,
+            size: link.sized_attachment.size,
+            human_size: format_size(link.sized_attachment.size),
+            text_to_remove: &link.text,
+        })
+        .collect();
+
+    Ok(vec![serde_json::to_string_pretty(&steps)?])
+}
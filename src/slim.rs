@@ -0,0 +1,229 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_derive::Deserialize;
+use serde_json::value::RawValue;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::Error;
+use std::path::Path;
+
+use crate::archive::{self, Archive, Entry};
+use crate::output::format_size;
+use crate::SizedAttachment;
+
+// The only field we need from each attachment record to tell whether it's being removed
+#[derive(Deserialize)]
+struct AttachmentRecord {
+    asset_url: String,
+}
+
+/// Writes a copy of the archive to `output_path` as a `.tar.gz`, leaving out the files for
+/// `attachments_by_size` and dropping their records from the `attachments_*.json` metadata files.
+/// Every other entry is copied across unchanged.
+///
+/// Returns a message for each attachment that was left out, followed by a summary.
+pub fn write_slimmed_archive(
+    archive: &Archive,
+    attachments_by_size: &[SizedAttachment],
+    output_path: &Path,
+    skip_invalid_metadata: bool,
+) -> Result<Vec<String>, Error> {
+    match archive {
+        // We read the tarball again as we write the slimmed-down copy, so writing over it would
+        // destroy the original before we'd finished reading it
+        Archive::Tarball { path, .. } => {
+            if output_path.exists() && output_path.canonicalize()? == path.canonicalize()? {
+                let error_message = format!(
+                    "Can't write the slimmed archive to `{}`, because that's the archive being slimmed. Please write it somewhere else.",
+                    output_path.display()
+                );
+                return Err(Error::other(error_message));
+            }
+        }
+        Archive::Directory(working_directory) => {
+            let output_directory = output_path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+
+            if output_directory
+                .canonicalize()?
+                .starts_with(working_directory.canonicalize()?)
+            {
+                let error_message = format!(
+                    "Can't write the slimmed archive to `{}`, because it's inside the archive directory `{}`. Please write it somewhere else.",
+                    output_path.display(),
+                    working_directory.display()
+                );
+                return Err(Error::other(error_message));
+            }
+        }
+    }
+
+    // Release assets are listed inside their releases' records, which we leave alone, so we only
+    // remove attachments
+    let attachments: Vec<&SizedAttachment> = attachments_by_size
+        .iter()
+        .filter(|sized_attachment| !sized_attachment.attachment.is_release_asset())
+        .collect();
+    let removed_asset_urls: HashSet<&str> = attachments
+        .iter()
+        .map(|sized_attachment| sized_attachment.attachment.asset_url.as_str())
+        .collect();
+    let removed_entry_paths: HashSet<String> = removed_asset_urls
+        .iter()
+        .map(|asset_url| archive::asset_entry_path(asset_url))
+        .collect();
+
+    // Write to a temporary file alongside the output, and only move it into place once it's
+    // complete, so a failed run doesn't leave a partial archive behind
+    let temporary_path = output_path.with_file_name(format!(
+        ".{}.partial",
+        output_path
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    ));
+
+    let result = write_entries(
+        archive,
+        &removed_asset_urls,
+        &removed_entry_paths,
+        &temporary_path,
+        skip_invalid_metadata,
+    )
+    .and_then(|()| fs::rename(&temporary_path, output_path));
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temporary_path);
+        return Err(e);
+    }
+
+    let mut messages: Vec<String> = attachments
+        .iter()
+        .map(|sized_attachment| {
+            let attachment = &sized_attachment.attachment;
+
+            format!(
+                "Removed {} ({}) - {}",
+                attachment.asset_name,
+                attachment.parent_url().unwrap_or("unknown parent"),
+                format_size(sized_attachment.size)
+            )
+        })
+        .collect();
+
+    let removed_size: u64 = attachments
+        .iter()
+        .map(|sized_attachment| sized_attachment.size)
+        .sum();
+    messages.push(format!(
+        "📦 Wrote `{}`, leaving out {} attachment(s) totalling {}",
+        output_path.display(),
+        attachments.len(),
+        format_size(removed_size)
+    ));
+
+    Ok(messages)
+}
+
+// Writes every entry in the archive to a new `.tar.gz` at `output_path`, except the files for
+// removed attachments, and with their records dropped from the `attachments_*.json` files
+fn write_entries(
+    archive: &Archive,
+    removed_asset_urls: &HashSet<&str>,
+    removed_entry_paths: &HashSet<String>,
+    output_path: &Path,
+    skip_invalid_metadata: bool,
+) -> Result<(), Error> {
+    let mut builder = tar::Builder::new(GzEncoder::new(
+        File::create(output_path)?,
+        Compression::default(),
+    ));
+
+    archive.for_each_entry(|entry| {
+        let Entry {
+            path,
+            entry_path,
+            mut header,
+            reader,
+        } = entry;
+
+        if removed_entry_paths.contains(&entry_path) {
+            return Ok(());
+        }
+
+        if archive::is_metadata_filename(&entry_path, "attachments") {
+            let mut contents = String::new();
+            reader.read_to_string(&mut contents)?;
+
+            let contents = match remove_records(&contents, removed_asset_urls) {
+                Ok(contents) => contents,
+                Err(e) if skip_invalid_metadata => {
+                    eprintln!(
+                        "⚠️ Could not parse metadata file `{}`: {}. Copying it unchanged...",
+                        entry_path, e
+                    );
+                    contents
+                }
+                Err(e) => {
+                    let error_message =
+                        format!("Could not parse metadata file `{}`: {}", entry_path, e);
+                    return Err(Error::other(error_message));
+                }
+            };
+
+            header.set_size(contents.len() as u64);
+            builder.append_data(&mut header, &path, contents.as_bytes())
+        } else {
+            builder.append_data(&mut header, &path, reader)
+        }
+    })?;
+
+    builder.into_inner()?.finish()?;
+
+    Ok(())
+}
+
+// Drops the records for removed attachments from the contents of an `attachments_*.json` file.
+// The rest of the file, including the whitespace between records, is kept exactly as it was.
+fn remove_records(
+    contents: &str,
+    removed_asset_urls: &HashSet<&str>,
+) -> Result<String, serde_json::Error> {
+    let records: Vec<&RawValue> = serde_json::from_str(contents)?;
+
+    // Where each record starts and ends in `contents`
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    for record in &records {
+        let start = record.get().as_ptr() as usize - contents.as_ptr() as usize;
+        spans.push((start, start + record.get().len()));
+    }
+
+    let (Some(&(first_start, _)), Some(&(_, last_end))) = (spans.first(), spans.last()) else {
+        return Ok(contents.to_string());
+    };
+
+    let mut slimmed = contents[..first_start].to_string();
+    let mut kept_any = false;
+
+    for (index, record) in records.iter().enumerate() {
+        let attachment: AttachmentRecord = serde_json::from_str(record.get())?;
+
+        if removed_asset_urls.contains(attachment.asset_url.as_str()) {
+            continue;
+        }
+
+        // Reuse the separator (e.g. `,\n  `) that came before the record in the original file
+        if kept_any {
+            slimmed.push_str(&contents[spans[index - 1].1..spans[index].0]);
+        }
+
+        slimmed.push_str(record.get());
+        kept_any = true;
+    }
+
+    slimmed.push_str(&contents[last_end..]);
+
+    Ok(slimmed)
+}
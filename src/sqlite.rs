@@ -0,0 +1,232 @@
+use rusqlite::{params, Connection};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use crate::output::format_size;
+use crate::parents::Parent;
+use crate::{Attachment, MissingAttachment, SizedAttachment};
+
+// The tables and indexes in the database. Attachments keep all of their metadata from the archive,
+// with the issue or pull request they belong to split out into `parents`, so it's easy to filter
+// by its state or when it was last updated. Attachments whose files are missing from the archive
+// are marked as `missing`, with no size or hash.
+const SCHEMA: &str = "
+CREATE TABLE parents (
+    id INTEGER PRIMARY KEY,
+    url TEXT NOT NULL UNIQUE,
+    number INTEGER,
+    title TEXT,
+    state TEXT,
+    updated_at TEXT
+);
+
+CREATE TABLE attachments (
+    id INTEGER PRIMARY KEY,
+    type TEXT NOT NULL,
+    url TEXT NOT NULL,
+    asset_name TEXT NOT NULL,
+    asset_content_type TEXT NOT NULL,
+    asset_url TEXT NOT NULL,
+    path TEXT NOT NULL,
+    size INTEGER,
+    sha256 TEXT,
+    missing INTEGER NOT NULL,
+    created_at TEXT NOT NULL,
+    user TEXT,
+    user_login TEXT,
+    repository TEXT,
+    parent_url TEXT,
+    pull_request TEXT,
+    issue TEXT,
+    issue_comment TEXT,
+    pull_request_review TEXT,
+    pull_request_review_comment TEXT,
+    commit_comment TEXT,
+    release TEXT,
+    parent_id INTEGER REFERENCES parents (id),
+    comment_kind TEXT,
+    comment_author TEXT
+);
+
+CREATE INDEX attachments_size ON attachments (size);
+CREATE INDEX attachments_asset_content_type ON attachments (asset_content_type);
+CREATE INDEX attachments_created_at ON attachments (created_at);
+CREATE INDEX attachments_repository ON attachments (repository);
+CREATE INDEX attachments_user_login ON attachments (user_login);
+CREATE INDEX attachments_sha256 ON attachments (sha256);
+CREATE INDEX attachments_parent_id ON attachments (parent_id);
+CREATE INDEX attachments_missing ON attachments (missing);
+CREATE INDEX parents_state ON parents (state);
+CREATE INDEX parents_updated_at ON parents (updated_at);
+";
+
+// An attachment to insert into the `attachments` table
+struct Row<'a> {
+    attachment: &'a Attachment,
+    path: &'a str,
+    size: Option<u64>,
+    sha256: Option<&'a str>,
+    parent: Option<&'a Parent>,
+}
+
+// Errors from SQLite are turned into IO errors, like everything else we return
+fn sqlite_error(output_path: &Path, e: rusqlite::Error) -> Error {
+    Error::other(format!(
+        "Could not write SQLite database `{}`: {}",
+        output_path.display(),
+        e
+    ))
+}
+
+/// Writes `attachments_by_size` and `missing_attachments` to a new SQLite database at
+/// `output_path`, with an `attachments` table holding each attachment's metadata, size and hash,
+/// and a `parents` table with the issues and pull requests they belong to. Any existing file at
+/// `output_path` is replaced.
+///
+/// Returns a summary message.
+pub fn write_database(
+    attachments_by_size: &[SizedAttachment],
+    missing_attachments: &[MissingAttachment],
+    output_path: &Path,
+) -> Result<Vec<String>, Error> {
+    match fs::remove_file(output_path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+
+    let mut connection = Connection::open(output_path).map_err(|e| sqlite_error(output_path, e))?;
+    let parents_count =
+        insert_attachments(&mut connection, attachments_by_size, missing_attachments)
+            .map_err(|e| sqlite_error(output_path, e))?;
+
+    let total_size: u64 = attachments_by_size
+        .iter()
+        .map(|sized_attachment| sized_attachment.size)
+        .sum();
+
+    let missing_message = if missing_attachments.is_empty() {
+        String::new()
+    } else {
+        format!(
+            " (plus {} whose file(s) are missing)",
+            missing_attachments.len()
+        )
+    };
+
+    Ok(vec![format!(
+        "🗃️  Wrote {} attachment(s) totalling {}{} and {} parent(s) to `{}`",
+        attachments_by_size.len(),
+        format_size(total_size),
+        missing_message,
+        parents_count,
+        output_path.display()
+    )])
+}
+
+// Creates the tables and fills them in a single transaction, returning how many parents were
+// written
+fn insert_attachments(
+    connection: &mut Connection,
+    attachments_by_size: &[SizedAttachment],
+    missing_attachments: &[MissingAttachment],
+) -> Result<usize, rusqlite::Error> {
+    let transaction = connection.transaction()?;
+    transaction.execute_batch(SCHEMA)?;
+
+    // Several attachments can belong to the same issue or pull request, so we only insert each
+    // parent once
+    let mut parent_ids: HashMap<&str, i64> = HashMap::new();
+
+    {
+        let mut insert_parent = transaction.prepare(
+            "INSERT INTO parents (url, number, title, state, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        let mut insert_attachment = transaction.prepare(
+            "INSERT INTO attachments (
+                type, url, asset_name, asset_content_type, asset_url, path, size, sha256,
+                missing, created_at, user, user_login, repository, parent_url, pull_request,
+                issue, issue_comment, pull_request_review, pull_request_review_comment,
+                commit_comment, release, parent_id, comment_kind, comment_author
+            ) VALUES (
+                ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18,
+                ?19, ?20, ?21, ?22, ?23, ?24
+            )",
+        )?;
+
+        // Each row is an attachment, where its file is (or should have been) and, if we found the
+        // file, its size and hash, alongside the issue or pull request it belongs to
+        let rows = attachments_by_size
+            .iter()
+            .map(|sized_attachment| Row {
+                attachment: &sized_attachment.attachment,
+                path: &sized_attachment.path,
+                size: Some(sized_attachment.size),
+                sha256: sized_attachment.sha256.as_deref(),
+                parent: sized_attachment.parent.as_ref(),
+            })
+            .chain(missing_attachments.iter().map(|missing_attachment| Row {
+                attachment: &missing_attachment.attachment,
+                path: &missing_attachment.path,
+                size: None,
+                sha256: None,
+                parent: missing_attachment.parent.as_ref(),
+            }));
+
+        for row in rows {
+            let attachment = row.attachment;
+            let parent = row.parent;
+
+            let parent_id = match parent {
+                Some(parent) => match parent_ids.get(parent.url.as_str()) {
+                    Some(parent_id) => Some(*parent_id),
+                    None => {
+                        let parent_id = insert_parent.insert(params![
+                            parent.url,
+                            parent.number.map(|number| number as i64),
+                            parent.title,
+                            parent.state.as_ref().map(|state| state.name()),
+                            parent.updated_at,
+                        ])?;
+                        parent_ids.insert(&parent.url, parent_id);
+                        Some(parent_id)
+                    }
+                },
+                None => None,
+            };
+
+            insert_attachment.execute(params![
+                attachment.r#type,
+                attachment.url,
+                attachment.asset_name,
+                attachment.asset_content_type,
+                attachment.asset_url,
+                row.path,
+                // SQLite integers are signed, but no file is anywhere near big enough to overflow
+                row.size.map(|size| size as i64),
+                row.sha256,
+                row.size.is_none(),
+                attachment.created_at,
+                attachment.user,
+                attachment.user_login(),
+                attachment.repository(),
+                attachment.parent_url(),
+                attachment.pull_request,
+                attachment.issue,
+                attachment.issue_comment,
+                attachment.pull_request_review,
+                attachment.pull_request_review_comment,
+                attachment.commit_comment,
+                attachment.release,
+                parent_id,
+                parent.and_then(|parent| parent.comment_kind),
+                parent.and_then(|parent| parent.comment_author.as_deref()),
+            ])?;
+        }
+    }
+
+    transaction.commit()?;
+
+    Ok(parent_ids.len())
+}
@@ -0,0 +1,193 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::SizedAttachment;
+
+// Used when we can't work out which group an attachment belongs in
+const UNKNOWN_GROUP_NAME: &str = "(unknown)";
+// The content type used for files that GitHub doesn't recognize, which tells us nothing about them
+const GENERIC_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// The ways `gaaa` can summarize attachments, rather than listing them individually.
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq)]
+pub enum GroupBy {
+    Repository,
+    User,
+    ContentType,
+}
+
+impl GroupBy {
+    /// The name of the field or column holding the group's name in the JSON and CSV output.
+    pub fn key_name(&self) -> &'static str {
+        match self {
+            GroupBy::Repository => "repository",
+            GroupBy::User => "user",
+            GroupBy::ContentType => "content_type",
+        }
+    }
+
+    fn group_name(&self, sized_attachment: &SizedAttachment) -> Option<String> {
+        match self {
+            GroupBy::Repository => sized_attachment.attachment.repository(),
+            GroupBy::User => sized_attachment
+                .attachment
+                .user_login()
+                .map(|login| login.to_string()),
+            GroupBy::ContentType => Some(content_type_group_name(sized_attachment)),
+        }
+    }
+
+    /// Returns the name of the group an attachment belongs in, e.g. `owner/repo`.
+    pub fn group_name_or_unknown(&self, sized_attachment: &SizedAttachment) -> String {
+        self.group_name(sized_attachment)
+            .unwrap_or_else(|| UNKNOWN_GROUP_NAME.to_string())
+    }
+}
+
+// Groups attachments by their MIME type, except for generic `application/octet-stream` files,
+// which we split up by their file extension instead (e.g. `application/octet-stream (.mov)`)
+fn content_type_group_name(sized_attachment: &SizedAttachment) -> String {
+    let attachment = &sized_attachment.attachment;
+    let content_type = attachment.asset_content_type.to_lowercase();
+
+    if !content_type.is_empty() && content_type != GENERIC_CONTENT_TYPE {
+        return content_type;
+    }
+
+    match Path::new(&attachment.asset_name).extension() {
+        Some(extension) => format!(
+            "{} (.{})",
+            GENERIC_CONTENT_TYPE,
+            extension.to_string_lossy().to_lowercase()
+        ),
+        None => GENERIC_CONTENT_TYPE.to_string(),
+    }
+}
+
+/// A set of attachments grouped together (e.g. by repository), with their combined size.
+#[derive(Debug, PartialEq)]
+pub struct Group {
+    pub name: String,
+    pub count: usize,
+    pub size: u64,
+}
+
+impl Group {
+    /// Returns whether the group is for a bot user, like `dependabot[bot]`. GitHub Apps' bot users
+    /// always have logins ending in `[bot]`.
+    pub fn is_bot(&self) -> bool {
+        self.name.ends_with("[bot]")
+    }
+}
+
+/// Totals up the number and size of attachments in each group as they're added, without keeping
+/// the attachments themselves.
+pub struct Grouper {
+    group_by: GroupBy,
+    groups_by_name: HashMap<String, Group>,
+}
+
+impl Grouper {
+    pub fn new(group_by: GroupBy) -> Grouper {
+        Grouper {
+            group_by,
+            groups_by_name: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, sized_attachment: &SizedAttachment) {
+        let name = self.group_by.group_name_or_unknown(sized_attachment);
+
+        let group = self.groups_by_name.entry(name.clone()).or_insert(Group {
+            name,
+            count: 0,
+            size: 0,
+        });
+        group.count += 1;
+        group.size += sized_attachment.size;
+    }
+
+    /// Returns the groups, largest first.
+    pub fn into_groups(self) -> Vec<Group> {
+        let mut groups: Vec<Group> = self.groups_by_name.into_values().collect();
+
+        // Sort by size, largest first, falling back to the name so the order is stable
+        groups.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+
+        groups
+    }
+}
+
+/// A set of attachments whose files have identical contents.
+#[derive(Debug)]
+pub struct DuplicateSet<'a> {
+    pub sha256: String,
+    pub size: u64,
+    pub attachments: Vec<&'a SizedAttachment>,
+}
+
+impl DuplicateSet<'_> {
+    /// Returns how many bytes would be saved by only keeping one copy of the file.
+    pub fn wasted_size(&self) -> u64 {
+        self.size * (self.attachments.len() as u64 - 1)
+    }
+}
+
+/// Finds attachments with identical contents, based on their SHA-256 hashes, returning the sets
+/// that waste the most space first. Attachments without a hash are ignored.
+pub fn find_duplicates(attachments_by_size: &[SizedAttachment]) -> Vec<DuplicateSet<'_>> {
+    let mut attachments_by_hash: HashMap<&str, Vec<&SizedAttachment>> = HashMap::new();
+
+    for sized_attachment in attachments_by_size {
+        if let Some(sha256) = &sized_attachment.sha256 {
+            attachments_by_hash
+                .entry(sha256)
+                .or_default()
+                .push(sized_attachment);
+        }
+    }
+
+    let mut duplicate_sets: Vec<DuplicateSet> = attachments_by_hash
+        .into_iter()
+        .filter(|(_, attachments)| attachments.len() > 1)
+        .map(|(sha256, attachments)| DuplicateSet {
+            sha256: sha256.to_string(),
+            size: attachments[0].size,
+            attachments,
+        })
+        .collect();
+
+    // Sort by wasted space, largest first, falling back to the hash so the order is stable
+    duplicate_sets.sort_by(|a, b| {
+        b.wasted_size()
+            .cmp(&a.wasted_size())
+            .then_with(|| a.sha256.cmp(&b.sha256))
+    });
+
+    duplicate_sets
+}
+
+/// A file in the archive's `attachments/` or `release_assets/` directory that isn't mentioned in
+/// any of the archive's metadata files.
+#[derive(Debug, PartialEq)]
+pub struct OrphanedFile {
+    pub path: String,
+    pub size: u64,
+}
+
+/// Finds the files in `asset_files` which aren't in `referenced_paths`, largest first.
+pub fn find_orphans(
+    asset_files: Vec<(String, u64)>,
+    referenced_paths: &HashSet<String>,
+) -> Vec<OrphanedFile> {
+    let mut orphaned_files: Vec<OrphanedFile> = asset_files
+        .into_iter()
+        .filter(|(path, _)| !referenced_paths.contains(path))
+        .map(|(path, size)| OrphanedFile { path, size })
+        .collect();
+
+    // Sort by size, largest first, falling back to the path so the order is stable
+    orphaned_files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+
+    orphaned_files
+}